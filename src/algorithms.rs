@@ -0,0 +1,362 @@
+//! Module to parse the algorithm attributes (C1, C2, C3) and the Algorithm information DO (FA).
+//!
+//! Read section 4.4.3.9 Algorithm Attributes and 4.4.3.11 Algorithm Information of the
+//! [OpenPGP-smart-card-application-3.4.1.pdf](https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf).
+
+use std::collections::HashMap;
+
+use crate::tlvs::{KeySlot, TLV};
+
+/// Import format of the RSA private keys, the last byte of the RSA algorithm attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RsaImportFormat {
+    /// e, p, q
+    Standard,
+    /// e, p, q and n
+    StandardWithModulus,
+    /// e, p, q, 1/q mod p, dp1, dq1
+    Crt,
+    /// e, p, q, 1/q mod p, dp1, dq1 and n
+    CrtWithModulus,
+    /// Any value not defined in the SPEC.
+    Unknown(u8),
+}
+
+impl RsaImportFormat {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            RsaImportFormat::Standard => 0x00,
+            RsaImportFormat::StandardWithModulus => 0x01,
+            RsaImportFormat::Crt => 0x02,
+            RsaImportFormat::CrtWithModulus => 0x03,
+            RsaImportFormat::Unknown(value) => value,
+        }
+    }
+
+    pub fn from_byte(value: u8) -> Self {
+        match value {
+            0x00 => RsaImportFormat::Standard,
+            0x01 => RsaImportFormat::StandardWithModulus,
+            0x02 => RsaImportFormat::Crt,
+            0x03 => RsaImportFormat::CrtWithModulus,
+            value => RsaImportFormat::Unknown(value),
+        }
+    }
+}
+
+/// The ECC based algorithms, identified by the first byte of the algorithm attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EccAlgorithm {
+    Ecdh,
+    Ecdsa,
+    EdDsa,
+}
+
+impl EccAlgorithm {
+    /// Returns the algorithm ID as used in the algorithm attributes.
+    pub fn id(self) -> u8 {
+        match self {
+            EccAlgorithm::Ecdh => 0x12,
+            EccAlgorithm::Ecdsa => 0x13,
+            EccAlgorithm::EdDsa => 0x16,
+        }
+    }
+}
+
+/// The elliptic curves known to the OpenPGP cards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Curve {
+    NistP256,
+    NistP384,
+    NistP521,
+    BrainpoolP256r1,
+    BrainpoolP384r1,
+    BrainpoolP512r1,
+    Secp256k1,
+    Ed25519,
+    Cv25519,
+    Ed448,
+    X448,
+    /// Any other curve, with the OID.
+    Unknown(Vec<u8>),
+}
+
+impl Curve {
+    /// Returns the OID of the curve, without the tag and length.
+    pub fn oid(&self) -> Vec<u8> {
+        match self {
+            Curve::NistP256 => vec![0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07],
+            Curve::NistP384 => vec![0x2B, 0x81, 0x04, 0x00, 0x22],
+            Curve::NistP521 => vec![0x2B, 0x81, 0x04, 0x00, 0x23],
+            Curve::BrainpoolP256r1 => vec![0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x07],
+            Curve::BrainpoolP384r1 => vec![0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0B],
+            Curve::BrainpoolP512r1 => vec![0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0D],
+            Curve::Secp256k1 => vec![0x2B, 0x81, 0x04, 0x00, 0x0A],
+            Curve::Ed25519 => vec![0x2B, 0x06, 0x01, 0x04, 0x01, 0xDA, 0x47, 0x0F, 0x01],
+            Curve::Cv25519 => vec![0x2B, 0x06, 0x01, 0x04, 0x01, 0x97, 0x55, 0x01, 0x05, 0x01],
+            Curve::Ed448 => vec![0x2B, 0x65, 0x71],
+            Curve::X448 => vec![0x2B, 0x65, 0x6F],
+            Curve::Unknown(oid) => oid.clone(),
+        }
+    }
+
+    /// Returns the curve for the given OID.
+    pub fn from_oid(oid: &[u8]) -> Self {
+        let known = [
+            Curve::NistP256,
+            Curve::NistP384,
+            Curve::NistP521,
+            Curve::BrainpoolP256r1,
+            Curve::BrainpoolP384r1,
+            Curve::BrainpoolP512r1,
+            Curve::Secp256k1,
+            Curve::Ed25519,
+            Curve::Cv25519,
+            Curve::Ed448,
+            Curve::X448,
+        ];
+        for curve in known {
+            if curve.oid() == oid {
+                return curve;
+            }
+        }
+        Curve::Unknown(oid.to_vec())
+    }
+
+    /// Returns the size of the private key (scalar) in bytes, `None` for unknown curves.
+    pub fn key_size(&self) -> Option<usize> {
+        match self {
+            Curve::NistP256
+            | Curve::BrainpoolP256r1
+            | Curve::Secp256k1
+            | Curve::Ed25519
+            | Curve::Cv25519 => Some(32),
+            Curve::NistP384 | Curve::BrainpoolP384r1 => Some(48),
+            Curve::BrainpoolP512r1 => Some(64),
+            Curve::NistP521 => Some(66),
+            Curve::Ed448 => Some(57),
+            Curve::X448 => Some(56),
+            Curve::Unknown(_) => None,
+        }
+    }
+}
+
+/// The algorithm attributes of a key slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlgorithmAttributes {
+    Rsa {
+        /// Length of the modulus in bits.
+        modulus_bits: u16,
+        /// Length of the public exponent in bits.
+        exponent_bits: u16,
+        import_format: RsaImportFormat,
+    },
+    Ecc {
+        algorithm: EccAlgorithm,
+        curve: Curve,
+        /// `true` if the public key is also required while importing the private key.
+        import_with_public_key: bool,
+    },
+    /// Anything we can not parse, with the raw bytes.
+    Unknown(Vec<u8>),
+}
+
+impl AlgorithmAttributes {
+    /// Parses the value of an algorithm attributes DO.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        if data.is_empty() {
+            return AlgorithmAttributes::Unknown(vec![]);
+        }
+        let algorithm = match data[0] {
+            0x01 => {
+                if data.len() < 5 {
+                    return AlgorithmAttributes::Unknown(data.to_vec());
+                }
+                let import_format = match data.get(5) {
+                    Some(value) => RsaImportFormat::from_byte(*value),
+                    None => RsaImportFormat::Standard,
+                };
+                return AlgorithmAttributes::Rsa {
+                    modulus_bits: u16::from_be_bytes([data[1], data[2]]),
+                    exponent_bits: u16::from_be_bytes([data[3], data[4]]),
+                    import_format,
+                };
+            }
+            0x12 => EccAlgorithm::Ecdh,
+            0x13 => EccAlgorithm::Ecdsa,
+            0x16 => EccAlgorithm::EdDsa,
+            _ => return AlgorithmAttributes::Unknown(data.to_vec()),
+        };
+        // The OID is followed by an optional import format byte, 0xFF means the public key is
+        // also required. Some cards add 0x00 for the standard format.
+        let mut oid = &data[1..];
+        let mut import_with_public_key = false;
+        if let Some(last) = oid.last() {
+            if *last == 0xFF || *last == 0x00 {
+                import_with_public_key = *last == 0xFF;
+                oid = &oid[..oid.len() - 1];
+            }
+        }
+        AlgorithmAttributes::Ecc {
+            algorithm,
+            curve: Curve::from_oid(oid),
+            import_with_public_key,
+        }
+    }
+
+    /// Returns the bytes to be used with
+    /// [create_apdu_put_algo_attributes](../apdus/fn.create_apdu_put_algo_attributes.html).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            AlgorithmAttributes::Rsa {
+                modulus_bits,
+                exponent_bits,
+                import_format,
+            } => {
+                let mut res = vec![0x01];
+                res.extend(modulus_bits.to_be_bytes().iter());
+                res.extend(exponent_bits.to_be_bytes().iter());
+                res.push(import_format.to_byte());
+                res
+            }
+            AlgorithmAttributes::Ecc {
+                algorithm,
+                curve,
+                import_with_public_key,
+            } => {
+                let mut res = vec![algorithm.id()];
+                res.extend(curve.oid());
+                if *import_with_public_key {
+                    res.push(0xFF);
+                }
+                res
+            }
+            AlgorithmAttributes::Unknown(data) => data.clone(),
+        }
+    }
+
+    /// Returns the algorithm attributes of the given slot from the Application related data (6E).
+    pub fn from_tlv(tlv: &TLV, slot: KeySlot) -> Option<Self> {
+        let t = tlv.find_tag(slot.algo_attributes_tag())?;
+        Some(AlgorithmAttributes::from_bytes(&t.v))
+    }
+}
+
+/// Parses the Algorithm information DO (FA) and returns the list of supported algorithm
+/// attributes for each key slot.
+///
+/// # Example
+///
+/// ```
+/// let resp = send_and_parse(&card, apdus::create_apdu_get_algorithm_information()).unwrap();
+/// let tlv = &tlvs::read_list(resp.get_data(), true)[0];
+/// let info = algorithms::parse_algorithm_information(tlv);
+/// for algo in &info[&tlvs::KeySlot::Signature] {
+///     println!("{:?}", algo);
+/// }
+/// ```
+pub fn parse_algorithm_information(tlv: &TLV) -> HashMap<KeySlot, Vec<AlgorithmAttributes>> {
+    let mut result: HashMap<KeySlot, Vec<AlgorithmAttributes>> = HashMap::new();
+    let fa = match tlv.find_tag(0xFA) {
+        Some(fa) => fa,
+        None => return result,
+    };
+    for sub in &fa.subs {
+        if let Some(slot) = KeySlot::from_algo_attributes_tag(sub.t) {
+            result
+                .entry(slot)
+                .or_default()
+                .push(AlgorithmAttributes::from_bytes(&sub.v));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tlvs::read_list;
+    use std::fs::File;
+    use std::io::Read;
+
+    fn get_my_tlv(filename: &str) -> TLV {
+        let mut f = File::open(filename).expect("no file found");
+        let mut buffer: Vec<u8> = Vec::new();
+        f.read_to_end(&mut buffer).unwrap();
+        read_list(buffer, true)[0].clone()
+    }
+
+    #[test]
+    fn test_parse_rsa_attributes() {
+        let big_box = get_my_tlv("./data/capabilities_tlv.binary");
+        let algo = AlgorithmAttributes::from_tlv(&big_box, KeySlot::Signature).unwrap();
+        assert_eq!(
+            algo,
+            AlgorithmAttributes::Rsa {
+                modulus_bits: 4096,
+                exponent_bits: 17,
+                import_format: RsaImportFormat::Standard,
+            }
+        );
+        assert_eq!(
+            algo.to_bytes(),
+            big_box.get_signature_algo_attributes().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_ecc_attributes() {
+        let big_box = get_my_tlv("./data/6e_information_for_25519.binary");
+        let sign = AlgorithmAttributes::from_tlv(&big_box, KeySlot::Signature).unwrap();
+        assert_eq!(
+            sign,
+            AlgorithmAttributes::Ecc {
+                algorithm: EccAlgorithm::EdDsa,
+                curve: Curve::Ed25519,
+                import_with_public_key: false,
+            }
+        );
+        let dec = AlgorithmAttributes::from_tlv(&big_box, KeySlot::Decryption).unwrap();
+        assert_eq!(
+            dec,
+            AlgorithmAttributes::Ecc {
+                algorithm: EccAlgorithm::Ecdh,
+                curve: Curve::Cv25519,
+                import_with_public_key: false,
+            }
+        );
+        let data = vec![0x13, 0x2B, 0x81, 0x04, 0x00, 0x22, 0xFF];
+        let algo = AlgorithmAttributes::from_bytes(&data);
+        assert_eq!(
+            algo,
+            AlgorithmAttributes::Ecc {
+                algorithm: EccAlgorithm::Ecdsa,
+                curve: Curve::NistP384,
+                import_with_public_key: true,
+            }
+        );
+        assert_eq!(algo.to_bytes(), data);
+    }
+
+    #[test]
+    fn test_parse_algorithm_information() {
+        let data = vec![
+            0xFA, 0x1C, 0xC1, 0x06, 0x01, 0x08, 0x00, 0x00, 0x11, 0x00, 0xC1, 0x0A, 0x16, 0x2B,
+            0x06, 0x01, 0x04, 0x01, 0xDA, 0x47, 0x0F, 0x01, 0xC2, 0x06, 0x01, 0x10, 0x00, 0x00,
+            0x11, 0x00,
+        ];
+        let tlv = &read_list(data, true)[0];
+        let info = parse_algorithm_information(tlv);
+        assert_eq!(info[&KeySlot::Signature].len(), 2);
+        assert_eq!(
+            info[&KeySlot::Signature][1],
+            AlgorithmAttributes::Ecc {
+                algorithm: EccAlgorithm::EdDsa,
+                curve: Curve::Ed25519,
+                import_with_public_key: false,
+            }
+        );
+        assert_eq!(info[&KeySlot::Decryption].len(), 1);
+        assert!(!info.contains_key(&KeySlot::Authentication));
+    }
+}
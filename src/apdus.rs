@@ -2,7 +2,12 @@
 //! predefined APDU structs.
 //!
 
+use crate::cardholder::Salutation;
+use crate::errors::TalktoSCError;
+use crate::pins::PinReference;
+use crate::tlvs::{self, KeySlot};
 use std::fmt;
+use std::time::SystemTime;
 
 /// Creates APDU to be used inside of our project.
 ///
@@ -33,10 +38,10 @@ impl fmt::Debug for APDU {
                 let hex = format!("0x{:X?}, ", value);
                 resp.push_str(&hex[..]);
             }
-            resp.push_str("]");
+            resp.push(']');
             start.push_str(&resp[..]);
         }
-        start.push_str("]");
+        start.push(']');
         f.debug_struct("APDU")
             .field("CLA", &self.cla)
             .field("INS", &self.ins)
@@ -53,10 +58,7 @@ impl APDU {
         let mut index = 1;
         let mut oindex = 0;
         let mut _cindex = 0;
-        let data = match inputdata {
-            Some(data) => data,
-            None => vec![],
-        };
+        let data = inputdata.unwrap_or_default();
         let length = data.len();
         let mut iapdus = Vec::new();
         while data.len() > index * 254 {
@@ -174,12 +176,56 @@ pub fn create_apdu_verify_pw3(pin: Vec<u8>) -> APDU {
     APDU::new(0x00, 0x20, 0x00, 0x83, Some(pin))
 }
 
+/// Creates a new APDU to ask the verification status of a pin, VERIFY without any data.
+///
+/// The card returns 0x90 0x00 if the pin is already verified, otherwise 0x63 0xCX where X is the
+/// number of remaining tries.
+pub fn create_apdu_get_verification_status(reference: PinReference) -> APDU {
+    let mut apdu = APDU::new(0x00, 0x20, 0x00, reference.p2(), None);
+    // No Lc or Le field at all
+    apdu.iapdus = vec![vec![0x00, 0x20, 0x00, reference.p2()]];
+    apdu
+}
+
 /// Creates a new APDU to change the PW1 pin.
 /// This can be used to reset the counter and set a pin.
+///
+/// This is actually RESET RETRY COUNTER after PW3 verification, same as
+/// [create_apdu_reset_retry_counter_by_admin].
+#[deprecated(
+    note = "Use create_apdu_reset_retry_counter_by_admin or create_apdu_change_reference_data_pw1"
+)]
 pub fn create_apdu_change_pw1(pin: Vec<u8>) -> APDU {
     APDU::new(0x00, 0x2C, 0x02, 0x81, Some(pin))
 }
 
+/// Creates a new APDU to change the PW1 pin with the current pin (CHANGE REFERENCE DATA).
+pub fn create_apdu_change_reference_data_pw1(pin: Vec<u8>, newpin: Vec<u8>) -> APDU {
+    let mut fullpin = pin.clone();
+    fullpin.extend(newpin.iter());
+    APDU::new(0x00, 0x24, 0x00, 0x81, Some(fullpin))
+}
+
+/// Creates a new APDU to unblock and set the PW1 pin with the Resetting Code (RESET RETRY
+/// COUNTER).
+pub fn create_apdu_reset_retry_counter_with_rc(resetting_code: Vec<u8>, newpin: Vec<u8>) -> APDU {
+    let mut data = resetting_code.clone();
+    data.extend(newpin.iter());
+    APDU::new(0x00, 0x2C, 0x00, 0x81, Some(data))
+}
+
+/// Creates a new APDU to unblock and set the PW1 pin (RESET RETRY COUNTER). PW3 must be verified
+/// before this.
+pub fn create_apdu_reset_retry_counter_by_admin(newpin: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0x2C, 0x02, 0x81, Some(newpin))
+}
+
+/// Creates a new APDU to set the Resetting Code (D3), an empty value clears it. PW3 must be
+/// verified before this.
+pub fn create_apdu_put_resetting_code(resetting_code: Vec<u8>) -> APDU {
+    create_apdu_put_data(0xD3, resetting_code, false)
+}
+
 /// Creates a new APDU to change the PW3 admin pin
 pub fn create_apdu_change_pw3(pin: Vec<u8>, newpin: Vec<u8>) -> APDU {
     let mut fullpin = pin.clone();
@@ -187,11 +233,39 @@ pub fn create_apdu_change_pw3(pin: Vec<u8>, newpin: Vec<u8>) -> APDU {
     APDU::new(0x00, 0x24, 0x00, 0x83, Some(fullpin))
 }
 
+/// Creates a new APDU to terminate the OpenPGP applet (TERMINATE DF). The card accepts this only
+/// after PW3 is verified or blocked.
+pub fn create_apdu_terminate_df() -> APDU {
+    APDU::new(0x00, 0xE6, 0x00, 0x00, None)
+}
+
+/// Creates a new APDU to activate the terminated OpenPGP applet (ACTIVATE FILE). This brings the
+/// applet back to the factory default state.
+pub fn create_apdu_activate_file() -> APDU {
+    APDU::new(0x00, 0x44, 0x00, 0x00, None)
+}
+
 /// Creates a new APDU to select the personal information from the card.
 pub fn create_apdu_personal_information() -> APDU {
     APDU::new(0x00, 0xCA, 0x00, 0x65, None)
 }
 
+/// Creates a new APDU to put the name (5B) of the card holder, encoded according to ISO/IEC
+/// 7501-1. Use [encode_name](../cardholder/fn.encode_name.html) to create the data.
+pub fn create_apdu_put_name(name: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0xDA, 0x00, 0x5B, Some(name))
+}
+
+/// Creates a new APDU to put the language preferences (5F2D) of the card holder.
+pub fn create_apdu_put_language(languages: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0xDA, 0x5F, 0x2D, Some(languages))
+}
+
+/// Creates a new APDU to put the salutation (5F35) of the card holder.
+pub fn create_apdu_put_salutation(salutation: Salutation) -> APDU {
+    APDU::new(0x00, 0xDA, 0x5F, 0x35, Some(vec![salutation.to_byte()]))
+}
+
 /// Creates the APDU to get the AID (16 bytes) from the card.
 /// See the table 4.2.1 in the SPEC 3.4.1 pdf
 ///
@@ -215,11 +289,99 @@ pub fn create_apdu_get_security_template() -> APDU {
     APDU::new(0x00, 0xCA, 0x00, 0x7A, None)
 }
 
+/// Creates new APDU to compute the digital signature (PSO: COMPUTE DIGITAL SIGNATURE) of the given
+/// data with the signature key. PW1 must be verified for signing before this.
+pub fn create_apdu_for_signing(data: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0x2A, 0x9E, 0x9A, Some(data))
+}
+
+/// Creates new APDU for the INTERNAL AUTHENTICATE operation with the authentication key. PW1 must
+/// be verified for other operations (0x82) before this.
+pub fn create_apdu_internal_authenticate(data: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0x88, 0x00, 0x00, Some(data))
+}
+
+/// Creates new APDU to generate a new key pair in the given slot (GENERATE ASYMMETRIC KEY PAIR).
+/// PW3 must be verified before this. The card returns the public key in a `7F49` template.
+pub fn create_apdu_generate_key(slot: KeySlot) -> APDU {
+    APDU::new(
+        0x00,
+        0x47,
+        0x80,
+        0x00,
+        Some(slot.control_reference_template()),
+    )
+}
+
+/// Creates new APDU to read the public key of the existing key in the given slot.
+pub fn create_apdu_read_public_key(slot: KeySlot) -> APDU {
+    APDU::new(
+        0x00,
+        0x47,
+        0x81,
+        0x00,
+        Some(slot.control_reference_template()),
+    )
+}
+
+/// Creates new APDU to import a private key with the extended header list (4D). PW3 must be
+/// verified before this.
+///
+/// If the card supports extended length, then we send a single big APDU, otherwise the data is
+/// sent via command chaining.
+pub fn create_apdu_import_key(data: Vec<u8>, extended_length: bool) -> APDU {
+    if extended_length {
+        APDU::create_big_apdu(0x00, 0xDB, 0x3F, 0xFF, data)
+    } else {
+        APDU::new(0x00, 0xDB, 0x3F, 0xFF, Some(data))
+    }
+}
+
 /// Creates new APDU for decryption operation
+///
+/// The data must already be formatted for the algorithm of the decryption key, see
+/// [create_decipher_input](../crypto/fn.create_decipher_input.html).
 pub fn create_apdu_for_decryption(data: Vec<u8>) -> APDU {
     APDU::new(0x00, 0x2A, 0x80, 0x86, Some(data))
 }
 
+/// Creates new APDU to encrypt the data with the AES key of the card (PSO: ENCIPHER). The data
+/// must be a multiple of the AES block size, the card returns the `02` padding indicator followed
+/// by the cryptogram. PW1 must be verified for other operations (0x82) before this.
+pub fn create_apdu_aes_encipher(data: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0x2A, 0x86, 0x80, Some(data))
+}
+
+/// Creates new APDU to decrypt the cryptogram with the AES key of the card (PSO: DECIPHER), with
+/// the `02` padding indicator before the cryptogram.
+pub fn create_apdu_aes_decipher(cryptogram: Vec<u8>) -> APDU {
+    let mut data = vec![0x02];
+    data.extend(cryptogram);
+    APDU::new(0x00, 0x2A, 0x80, 0x86, Some(data))
+}
+
+/// Creates new APDU to put the AES key (D5, 16 or 32 bytes) for PSO: ENCIPHER and DECIPHER. PW3
+/// must be verified before this.
+pub fn create_apdu_put_aes_key(key: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0xDA, 0x00, 0xD5, Some(key))
+}
+
+/// Creates new APDU for MANAGE SECURITY ENVIRONMENT, to select the `key` used by the given
+/// `operation` (PSO: DECIPHER for [KeySlot::Decryption], INTERNAL AUTHENTICATE for
+/// [KeySlot::Authentication]).
+///
+/// Only the decryption and the authentication keys can be swapped, the card answers `6A80`
+/// otherwise.
+pub fn create_apdu_manage_security_environment(operation: KeySlot, key: KeySlot) -> APDU {
+    APDU::new(
+        0x00,
+        0x22,
+        0x41,
+        operation.control_reference_template()[0],
+        Some(vec![0x83, 0x01, key.key_ref()]),
+    )
+}
+
 /// Creates new APDU only for reading more data from the card
 ///
 /// Use this when the previous response is (0x61 length)
@@ -242,6 +404,158 @@ pub fn create_apdu_for_reading(length: u8) -> APDU {
     }
 }
 
+/// Creates new APDU to get a challenge (random bytes) of the given length from the card (GET
+/// CHALLENGE). Lengths bigger than 256 bytes use the extended length Le field.
+pub fn create_apdu_get_challenge(length: u16) -> APDU {
+    let mut apdu = APDU::new(0x00, 0x84, 0x00, 0x00, None);
+    let res = if length > 256 {
+        let length = length.to_be_bytes();
+        vec![0x00, 0x84, 0x00, 0x00, 0x00, length[0], length[1]]
+    } else {
+        // 0x00 means 256 bytes
+        vec![0x00, 0x84, 0x00, 0x00, length as u8]
+    };
+    apdu.iapdus = vec![res];
+    apdu
+}
+
+/// Creates new APDU to select the given occurrence (starting from 0) of a DO for the next GET
+/// DATA or PUT DATA (SELECT DATA). The cardholder certificate DO `7F21` has one occurrence for
+/// each key slot.
+pub fn create_apdu_select_data(occurrence: u8, tag: u16) -> APDU {
+    let tag = tag.to_be_bytes();
+    APDU::new(
+        0x00,
+        0xA5,
+        occurrence,
+        0x04,
+        Some(vec![0x60, 0x04, 0x5C, 0x02, tag[0], tag[1]]),
+    )
+}
+
+/// Creates new APDU to read the selected cardholder certificate (7F21). With extended length the
+/// card can send the whole certificate in one response, otherwise it comes in many (0x61 LENGTH)
+/// parts.
+pub fn create_apdu_get_cardholder_certificate(extended_length: bool) -> APDU {
+    let mut apdu = APDU::new(0x00, 0xCA, 0x7F, 0x21, None);
+    if extended_length {
+        apdu.iapdus = vec![vec![0x00, 0xCA, 0x7F, 0x21, 0x00, 0x00, 0x00]];
+    }
+    apdu
+}
+
+/// Creates new APDU to write the selected cardholder certificate (7F21), as extended length APDU
+/// or as chained APDUs. PW3 must be verified before this.
+pub fn create_apdu_put_cardholder_certificate(data: Vec<u8>, extended_length: bool) -> APDU {
+    if extended_length {
+        APDU::create_big_apdu(0x00, 0xDA, 0x7F, 0x21, data)
+    } else {
+        APDU::new(0x00, 0xDA, 0x7F, 0x21, Some(data))
+    }
+}
+
+/// Creates new APDU to delete the selected cardholder certificate (7F21), PUT DATA without any
+/// data. PW3 must be verified before this.
+pub fn create_apdu_delete_cardholder_certificate() -> APDU {
+    let mut apdu = APDU::new(0x00, 0xDA, 0x7F, 0x21, None);
+    // No Lc or Le field at all
+    apdu.iapdus = vec![vec![0x00, 0xDA, 0x7F, 0x21]];
+    apdu
+}
+
+/// Creates new APDU to read the private use DO (0101 to 0104).
+pub fn create_apdu_get_private_use_do(tag: u16) -> APDU {
+    let tag = tag.to_be_bytes();
+    APDU::new(0x00, 0xCA, tag[0], tag[1], None)
+}
+
+/// Creates new APDU to write the private use DO (0101 to 0104), an empty value clears it.
+pub fn create_apdu_put_private_use_do(tag: u16, data: Vec<u8>) -> APDU {
+    create_apdu_put_data(tag, data, false)
+}
+
+/// Creates new APDU to read the KDF-DO (F9).
+pub fn create_apdu_get_kdf_do() -> APDU {
+    APDU::new(0x00, 0xCA, 0x00, 0xF9, None)
+}
+
+/// Creates new APDU to write the KDF-DO (F9). PW3 must be verified before this.
+pub fn create_apdu_put_kdf_do(data: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0xDA, 0x00, 0xF9, Some(data))
+}
+
+/// Creates new APDU to put the User Interaction Flag (touch policy) of the given slot. PW3 must
+/// be verified before this.
+pub fn create_apdu_put_uif(slot: KeySlot, data: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0xDA, 0x00, slot.uif_tag() as u8, Some(data))
+}
+
+/// Creates new APDU for the YubiKey vendor command to attest the key of the given slot. The
+/// YubiKey puts the attestation certificate in the cardholder certificate DO of the slot.
+pub fn create_apdu_yubikey_attest(slot: KeySlot) -> APDU {
+    let mut apdu = APDU::new(0x80, 0xFB, slot.key_ref(), 0x00, None);
+    // No Lc or Le field at all
+    apdu.iapdus = vec![vec![0x80, 0xFB, slot.key_ref(), 0x00]];
+    apdu
+}
+
+/// Creates new APDU to read the certificate of the attestation key (FC) of the YubiKey.
+pub fn create_apdu_get_attestation_certificate() -> APDU {
+    APDU::new(0x00, 0xCA, 0x00, 0xFC, None)
+}
+
+/// Creates a new APDU for the YubiKey GET VERSION, the firmware version in BCD.
+pub fn create_apdu_yubikey_get_version() -> APDU {
+    APDU::new(0x00, 0xF1, 0x00, 0x00, None)
+}
+
+/// Creates a new APDU for the YubiKey SET PIN RETRIES, with the retry counters of PW1, the
+/// Resetting Code and PW3.
+pub fn create_apdu_yubikey_set_pin_retries(pw1: u8, rc: u8, pw3: u8) -> APDU {
+    APDU::new(0x00, 0xF2, 0x00, 0x00, Some(vec![pw1, rc, pw3]))
+}
+
+/// Creates new APDU to put the SM encryption key K_ENC (D1). PW3 must be verified before this.
+pub fn create_apdu_put_sm_enc_key(key: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0xDA, 0x00, 0xD1, Some(key))
+}
+
+/// Creates new APDU to put the SM MAC key K_MAC (D2). PW3 must be verified before this.
+pub fn create_apdu_put_sm_mac_key(key: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0xDA, 0x00, 0xD2, Some(key))
+}
+
+/// Creates new APDU to put both SM keys via the SM key container (F4), the data has the `D1` and
+/// `D2` DOs. PW3 must be verified before this.
+pub fn create_apdu_put_sm_keys(data: Vec<u8>) -> APDU {
+    APDU::new(0x00, 0xDA, 0x00, 0xF4, Some(data))
+}
+
+/// Creates a new APDU for PUT DATA (even INS DA) of the DO with the given tag. Empty data gives
+/// the APDU without Lc and Le, which clears the DO.
+pub fn create_apdu_put_data(tag: u16, data: Vec<u8>, extended_length: bool) -> APDU {
+    let tag = tag.to_be_bytes();
+    if data.is_empty() {
+        let mut apdu = APDU::new(0x00, 0xDA, tag[0], tag[1], None);
+        // No Lc or Le field at all
+        apdu.iapdus = vec![vec![0x00, 0xDA, tag[0], tag[1]]];
+        apdu
+    } else if extended_length {
+        APDU::create_big_apdu(0x00, 0xDA, tag[0], tag[1], data)
+    } else {
+        APDU::new(0x00, 0xDA, tag[0], tag[1], Some(data))
+    }
+}
+
+/// Creates a new APDU for PUT DATA with the odd INS (DB), the data is the whole TLV of the DO.
+pub fn create_apdu_put_data_odd(data: Vec<u8>, extended_length: bool) -> APDU {
+    if extended_length {
+        APDU::create_big_apdu(0x00, 0xDB, 0x3F, 0xFF, data)
+    } else {
+        APDU::new(0x00, 0xDB, 0x3F, 0xFF, Some(data))
+    }
+}
+
 /// Creates Management selection APDU
 pub fn create_apdu_management_selection() -> APDU {
     APDU::new(
@@ -288,3 +602,54 @@ pub fn create_usb_otp_disable() -> APDU {
 pub fn create_apdu_for_algo_attributes(data: Vec<u8>) -> APDU {
     APDU::create_big_apdu(0x00, 0xDA, 0x00, 0xC2, data)
 }
+
+/// Creates big APDU to put algorithm attributes data for the given key slot in to the card
+pub fn create_apdu_put_algo_attributes(slot: KeySlot, data: Vec<u8>) -> APDU {
+    APDU::create_big_apdu(0x00, 0xDA, 0x00, slot.algo_attributes_tag() as u8, data)
+}
+
+/// Creates a new APDU to get the Algorithm information (FA), the list of supported algorithm
+/// attributes for each key slot. Only available in the cards following the SPEC 3.4 or later.
+pub fn create_apdu_get_algorithm_information() -> APDU {
+    APDU::new(0x00, 0xCA, 0x00, 0xFA, None)
+}
+
+/// Creates a new APDU to put the fingerprint (20 bytes) of the key in the given slot.
+pub fn create_apdu_put_fingerprint(slot: KeySlot, fingerprint: Vec<u8>) -> APDU {
+    APDU::new(
+        0x00,
+        0xDA,
+        0x00,
+        slot.fingerprint_tag() as u8,
+        Some(fingerprint),
+    )
+}
+
+/// Creates a new APDU to put the CA fingerprint (20 bytes) for the given slot.
+pub fn create_apdu_put_ca_fingerprint(slot: KeySlot, fingerprint: Vec<u8>) -> APDU {
+    APDU::new(
+        0x00,
+        0xDA,
+        0x00,
+        slot.ca_fingerprint_tag() as u8,
+        Some(fingerprint),
+    )
+}
+
+/// Creates a new APDU to put the generation time of the key in the given slot.
+///
+/// The v4 fingerprint of the OpenPGP key depends on the creation time, so this must be the same
+/// value used to calculate the fingerprint put via [create_apdu_put_fingerprint]. Fails for the
+/// times the card can not store, see [tlvs::encode_timestamp].
+pub fn create_apdu_put_generation_timestamp(
+    slot: KeySlot,
+    timestamp: SystemTime,
+) -> Result<APDU, TalktoSCError> {
+    Ok(APDU::new(
+        0x00,
+        0xDA,
+        0x00,
+        slot.generation_timestamp_tag() as u8,
+        Some(tlvs::encode_timestamp(timestamp)?),
+    ))
+}
@@ -0,0 +1,363 @@
+//! Module for the OpenPGP key attestation of the YubiKey (firmware 5.2 or later).
+//!
+//! The YubiKey signs a certificate for the key in the given slot with its attestation key, and
+//! puts it in the cardholder certificate DO (7F21) of that slot. The certificate of the
+//! attestation key itself is in the DO `FC`. Older YubiKeys have it issued directly by the Yubico
+//! OpenPGP Attestation CA, the newer ones by an intermediate CA under the Yubico Attestation Root.
+//! Read <https://developers.yubico.com/PGP/Attestation.html> for the details.
+//!
+//! With the `yubico-roots` feature the Yubico roots are bundled from `data/yubico/`, see
+//! [verify_yubikey_attestation]. Else pass the trusted roots to [verify_attestation].
+
+use x509_parser::prelude::*;
+
+use crate::apdus;
+use crate::certificates;
+use crate::errors::TalktoSCError;
+use crate::tlvs::{KeySlot, KeyStatus};
+use crate::uif::TouchPolicy;
+use crate::yubikey;
+use crate::CardTransport;
+
+/// Firmware version of the YubiKey (1.3.6.1.4.1.41482.3.3).
+const OID_FIRMWARE: &str = "1.3.6.1.4.1.41482.3.3";
+/// Serial number of the YubiKey (1.3.6.1.4.1.41482.3.7).
+const OID_SERIAL: &str = "1.3.6.1.4.1.41482.3.7";
+/// Name of the cardholder (1.3.6.1.4.1.41482.5.1).
+const OID_CARDHOLDER: &str = "1.3.6.1.4.1.41482.5.1";
+/// Source of the key, generated or imported (1.3.6.1.4.1.41482.5.2).
+const OID_KEY_SOURCE: &str = "1.3.6.1.4.1.41482.5.2";
+/// Version of the OpenPGP application (1.3.6.1.4.1.41482.5.3).
+const OID_OPENPGP_VERSION: &str = "1.3.6.1.4.1.41482.5.3";
+/// PIN policy of the signature key (1.3.6.1.4.1.41482.5.4).
+const OID_PIN_POLICY: &str = "1.3.6.1.4.1.41482.5.4";
+/// Touch policy of the key (1.3.6.1.4.1.41482.5.5).
+const OID_TOUCH_POLICY: &str = "1.3.6.1.4.1.41482.5.5";
+
+/// The PIN policy in the attestation, same as the first byte of the PW status bytes (C4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinPolicy {
+    /// PW1 is needed for every signature.
+    Always,
+    /// PW1 is valid for many signatures, till the card is reset.
+    Once,
+    Unknown(u8),
+}
+
+impl From<u8> for PinPolicy {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => PinPolicy::Always,
+            0x01 => PinPolicy::Once,
+            value => PinPolicy::Unknown(value),
+        }
+    }
+}
+
+/// The properties of the key attested by the YubiKey. Missing extensions are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    /// Serial number of the YubiKey.
+    pub serial: Option<u32>,
+    /// Firmware version as (major, minor, patch).
+    pub firmware: Option<(u8, u8, u8)>,
+    pub touch_policy: Option<TouchPolicy>,
+    pub pin_policy: Option<PinPolicy>,
+    /// [KeyStatus::Generated] if the key never left the YubiKey.
+    pub key_source: Option<KeyStatus>,
+    pub cardholder: Option<String>,
+    /// Version of the OpenPGP application as (major, minor).
+    pub openpgp_version: Option<(u8, u8)>,
+}
+
+impl Attestation {
+    /// Tells if the key was generated on the YubiKey.
+    pub fn is_generated(&self) -> bool {
+        self.key_source == Some(KeyStatus::Generated)
+    }
+}
+
+/// Asks the YubiKey to attest the key of the given slot, and returns the attestation certificate
+/// (DER). This overwrites the cardholder certificate of the slot.
+///
+/// # Example
+///
+/// ```
+/// let cert = attestation::attest_key(&card, tlvs::KeySlot::Signature).unwrap();
+/// let device = attestation::get_attestation_certificate(&card).unwrap();
+/// let attestation = attestation::verify_yubikey_attestation(&cert, &[&device]).unwrap();
+/// assert!(attestation.is_generated());
+/// ```
+pub fn attest_key(card: &impl CardTransport, slot: KeySlot) -> Result<Vec<u8>, TalktoSCError> {
+    if slot == KeySlot::Attestation {
+        return Err(TalktoSCError::InvalidDataError(String::from(
+            "The attestation key can not attest itself",
+        )));
+    }
+    yubikey::check_yubikey(card, "the YubiKey attestation")?;
+    crate::send_and_parse(card, apdus::create_apdu_yubikey_attest(slot))?.check()?;
+    certificates::read_certificate(card, slot)?.ok_or(TalktoSCError::MissingTagError(
+        certificates::CARDHOLDER_CERTIFICATE_TAG,
+    ))
+}
+
+/// Returns the certificate (DER) of the attestation key of the YubiKey (FC), the issuer of the
+/// certificates from [attest_key].
+pub fn get_attestation_certificate(card: &impl CardTransport) -> Result<Vec<u8>, TalktoSCError> {
+    yubikey::check_yubikey(card, "the YubiKey attestation")?;
+    let resp = crate::send_and_read_all(card, apdus::create_apdu_get_attestation_certificate())?
+        .check()?;
+    let data = resp.get_data();
+    if data.is_empty() {
+        return Err(TalktoSCError::MissingTagError(0xFC));
+    }
+    Ok(data)
+}
+
+fn parse_certificate(data: &[u8]) -> Result<X509Certificate<'_>, TalktoSCError> {
+    X509Certificate::from_der(data)
+        .map(|(_, cert)| cert)
+        .map_err(|err| TalktoSCError::InvalidDataError(err.to_string()))
+}
+
+/// Checks that `issuer` may issue certificates: basicConstraints with CA, and keyCertSign in the
+/// keyUsage if the certificate limits the usage.
+fn check_ca(issuer: &X509Certificate) -> Result<(), TalktoSCError> {
+    let not_ca = || TalktoSCError::AttestationError(format!("{} is not a CA", issuer.subject()));
+    match issuer.basic_constraints() {
+        Ok(Some(constraints)) if constraints.value.ca => (),
+        _ => return Err(not_ca()),
+    }
+    match issuer.key_usage() {
+        Ok(Some(usage)) if !usage.value.key_cert_sign() => Err(not_ca()),
+        Ok(_) => Ok(()),
+        Err(_) => Err(not_ca()),
+    }
+}
+
+/// Checks that `cert` is valid now and signed by `issuer`, and that `issuer` is a CA.
+fn check_issued_by(cert: &X509Certificate, issuer: &X509Certificate) -> Result<(), TalktoSCError> {
+    if cert.issuer().as_raw() != issuer.subject().as_raw() {
+        return Err(TalktoSCError::AttestationError(format!(
+            "{} is not issued by {}",
+            cert.subject(),
+            issuer.subject()
+        )));
+    }
+    check_ca(issuer)?;
+    if !cert.validity().is_valid() {
+        return Err(TalktoSCError::AttestationError(format!(
+            "{} is not valid now",
+            cert.subject()
+        )));
+    }
+    cert.verify_signature(Some(issuer.public_key()))
+        .map_err(|err| TalktoSCError::AttestationError(format!("{}: {}", cert.subject(), err)))
+}
+
+/// Verifies the chain of the attestation certificate up to one of the trusted roots, all DER
+/// encoded. The `chain` has the issuers between them, starting with the certificate of the
+/// attestation key (FC) and then any intermediate CA. Returns the attested properties of the key.
+pub fn verify_attestation(
+    attestation: &[u8],
+    chain: &[&[u8]],
+    roots: &[&[u8]],
+) -> Result<Attestation, TalktoSCError> {
+    let mut cert = parse_certificate(attestation)?;
+    for issuer in chain {
+        let issuer = parse_certificate(issuer)?;
+        check_issued_by(&cert, &issuer)?;
+        cert = issuer;
+    }
+
+    let mut last_error = TalktoSCError::AttestationError(String::from("No trusted root"));
+    for root in roots {
+        let root = parse_certificate(root)?;
+        let res = check_issued_by(&root, &root).and_then(|_| check_issued_by(&cert, &root));
+        match res {
+            Ok(()) => return parse_attestation(attestation),
+            Err(err) => last_error = err,
+        }
+    }
+    Err(last_error)
+}
+
+/// The Yubico OpenPGP Attestation CA and the Yubico Attestation Root 1, from
+/// <https://developers.yubico.com/PKI/>.
+#[cfg(feature = "yubico-roots")]
+pub const YUBICO_ROOTS: &[&[u8]] = &[
+    include_bytes!("../data/yubico/opgp-attestation-ca.der"),
+    include_bytes!("../data/yubico/yubico-ca-1.der"),
+];
+
+/// Same as [verify_attestation], with the bundled Yubico roots.
+#[cfg(feature = "yubico-roots")]
+pub fn verify_yubikey_attestation(
+    attestation: &[u8],
+    chain: &[&[u8]],
+) -> Result<Attestation, TalktoSCError> {
+    verify_attestation(attestation, chain, YUBICO_ROOTS)
+}
+
+/// Parses the attested properties from the attestation certificate, without verifying it.
+pub fn parse_attestation(attestation: &[u8]) -> Result<Attestation, TalktoSCError> {
+    let cert = parse_certificate(attestation)?;
+    let mut res = Attestation {
+        serial: None,
+        firmware: None,
+        touch_policy: None,
+        pin_policy: None,
+        key_source: None,
+        cardholder: None,
+        openpgp_version: None,
+    };
+    for ext in cert.extensions() {
+        let value = ext.value;
+        match &ext.oid.to_id_string()[..] {
+            OID_FIRMWARE if value.len() == 3 => res.firmware = Some((value[0], value[1], value[2])),
+            OID_SERIAL => res.serial = parse_serial(value),
+            OID_CARDHOLDER => res.cardholder = Some(String::from_utf8_lossy(value).into_owned()),
+            OID_KEY_SOURCE if !value.is_empty() => {
+                res.key_source = Some(match value[0] {
+                    0x00 => KeyStatus::Imported,
+                    0x01 => KeyStatus::Generated,
+                    value => KeyStatus::Unknown(value),
+                })
+            }
+            OID_OPENPGP_VERSION if value.len() == 2 => {
+                res.openpgp_version = Some((value[0], value[1]))
+            }
+            OID_PIN_POLICY if !value.is_empty() => res.pin_policy = Some(PinPolicy::from(value[0])),
+            OID_TOUCH_POLICY if !value.is_empty() => {
+                res.touch_policy = Some(TouchPolicy::from(value[0]))
+            }
+            _ => (),
+        }
+    }
+    Ok(res)
+}
+
+/// The serial number is a DER INTEGER, or the plain big endian bytes.
+fn parse_serial(value: &[u8]) -> Option<u32> {
+    let value = match value {
+        [0x02, length, rest @ ..] if *length as usize == rest.len() => rest,
+        _ => value,
+    };
+    if value.len() > 5 || (value.len() == 5 && value[0] != 0x00) {
+        return None;
+    }
+    Some(value.iter().fold(0u32, |acc, x| (acc << 8) | *x as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A test chain made with openssl, with the same extensions as the YubiKey uses.
+    fn read_chain() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        (
+            fs::read("./data/attestation_signature.der").unwrap(),
+            fs::read("./data/attestation_intermediate.der").unwrap(),
+            fs::read("./data/attestation_root.der").unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_verify_attestation() {
+        let (cert, intermediate, root) = read_chain();
+        let attestation = verify_attestation(&cert, &[&intermediate], &[&root]).unwrap();
+        assert_eq!(attestation.serial, Some(0x01020304));
+        assert_eq!(attestation.firmware, Some((5, 4, 3)));
+        assert_eq!(attestation.touch_policy, Some(TouchPolicy::Cached));
+        assert_eq!(attestation.pin_policy, Some(PinPolicy::Once));
+        assert_eq!(attestation.cardholder, Some(String::from("Kushal")));
+        assert_eq!(attestation.openpgp_version, Some((3, 4)));
+        assert!(attestation.is_generated());
+    }
+
+    #[test]
+    fn test_verify_attestation_wrong_chain() {
+        let (cert, intermediate, root) = read_chain();
+        // The root did not sign the attestation certificate.
+        assert!(matches!(
+            verify_attestation(&cert, &[&root], &[&root]),
+            Err(TalktoSCError::AttestationError(_))
+        ));
+        assert!(verify_attestation(&cert, &[], &[&root]).is_err());
+        // Not trusting anything
+        assert!(verify_attestation(&cert, &[&intermediate], &[]).is_err());
+        // The intermediate is not a trusted root.
+        assert!(verify_attestation(&cert, &[&intermediate], &[&intermediate]).is_err());
+        assert!(verify_attestation(&cert[1..], &[&intermediate], &[&root]).is_err());
+    }
+
+    // The chain of the newer YubiKeys: root, intermediate CA, the attestation key (FC) and the
+    // attested key, also made with openssl.
+    #[test]
+    fn test_verify_attestation_long_chain() {
+        let cert = fs::read("./data/attestation_chain_decryption.der").unwrap();
+        let device = fs::read("./data/attestation_chain_device.der").unwrap();
+        let intermediate = fs::read("./data/attestation_chain_intermediate.der").unwrap();
+        let root = fs::read("./data/attestation_chain_root.der").unwrap();
+        let attestation = verify_attestation(&cert, &[&device, &intermediate], &[&root]).unwrap();
+        assert_eq!(attestation.serial, Some(0x00BC614E));
+        assert_eq!(attestation.firmware, Some((5, 7, 4)));
+        assert_eq!(attestation.touch_policy, Some(TouchPolicy::On));
+        assert_eq!(attestation.pin_policy, Some(PinPolicy::Always));
+        assert!(!attestation.is_generated());
+        // A missing or misplaced intermediate breaks the chain.
+        assert!(verify_attestation(&cert, &[&device], &[&root]).is_err());
+        assert!(verify_attestation(&cert, &[&intermediate, &device], &[&root]).is_err());
+    }
+
+    #[test]
+    fn test_verify_attestation_issuer_not_ca() {
+        let cert = fs::read("./data/attestation_chain_not_ca_issued.der").unwrap();
+        let not_ca = fs::read("./data/attestation_chain_not_ca.der").unwrap();
+        let intermediate = fs::read("./data/attestation_chain_intermediate.der").unwrap();
+        let root = fs::read("./data/attestation_chain_root.der").unwrap();
+        match verify_attestation(&cert, &[&not_ca, &intermediate], &[&root]) {
+            Err(TalktoSCError::AttestationError(err)) => assert!(err.contains("not a CA")),
+            res => panic!("Expected a CA error, got {:?}", res),
+        }
+        // The attested key can not be a root either.
+        let (cert, _, _) = read_chain();
+        assert!(verify_attestation(&cert, &[], &[&cert]).is_err());
+    }
+
+    #[test]
+    fn test_parse_serial() {
+        assert_eq!(
+            parse_serial(&[0x02, 0x03, 0x01, 0x02, 0x03]),
+            Some(0x010203)
+        );
+        assert_eq!(
+            parse_serial(&[0x02, 0x05, 0x00, 0xFF, 0x02, 0x03, 0x04]),
+            Some(0xFF020304)
+        );
+        assert_eq!(parse_serial(&[0x00, 0xBC, 0x61, 0x4E]), Some(0x00BC614E));
+    }
+
+    // Run with `cargo test --features yubico-roots`, needs the DER files in data/yubico/.
+    #[cfg(feature = "yubico-roots")]
+    #[test]
+    fn test_yubico_roots() {
+        for root in YUBICO_ROOTS {
+            let cert = parse_certificate(root).unwrap();
+            check_ca(&cert).unwrap();
+            check_issued_by(&cert, &cert).unwrap();
+        }
+        // The test chain is not from Yubico.
+        let (cert, intermediate, _) = read_chain();
+        assert!(verify_yubikey_attestation(&cert, &[&intermediate]).is_err());
+    }
+
+    #[test]
+    fn test_attest_apdus() {
+        let apdu = apdus::create_apdu_yubikey_attest(KeySlot::Decryption);
+        assert_eq!(apdu.iapdus, vec![vec![0x80, 0xFB, 0x02, 0x00]]);
+        let apdu = apdus::create_apdu_get_attestation_certificate();
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xCA, 0x00, 0xFC, 0x00]]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Module to read and write the Cardholder related data (65) of the card.
+//!
+//! The name (5B) is stored according to ISO/IEC 7501-1, the same format GnuPG uses. The surname
+//! comes first, followed by `<<` and the given names. Any space inside of the surname or the given
+//! names is replaced by a single `<` filler.
+
+use crate::apdus::{self, APDU};
+use crate::errors::TalktoSCError;
+use crate::tlvs::TLV;
+
+/// Maximum length of the name (5B) in bytes.
+pub const MAX_NAME_LENGTH: usize = 39;
+
+/// Salutation (5F35) as defined in ISO 5218, stored as a single ASCII digit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Salutation {
+    NotAnnounced,
+    Mr,
+    Mrs,
+    NotApplicable,
+}
+
+impl Salutation {
+    /// Returns the byte to store in the card.
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Salutation::NotAnnounced => 0x30,
+            Salutation::Mr => 0x31,
+            Salutation::Mrs => 0x32,
+            Salutation::NotApplicable => 0x39,
+        }
+    }
+
+    /// Returns the salutation from the byte stored in the card, unknown values are treated as
+    /// `NotAnnounced`.
+    pub fn from_byte(value: u8) -> Self {
+        match value {
+            0x31 => Salutation::Mr,
+            0x32 => Salutation::Mrs,
+            0x39 => Salutation::NotApplicable,
+            _ => Salutation::NotAnnounced,
+        }
+    }
+}
+
+/// The Cardholder related data (65) of the card.
+///
+/// # Example
+///
+/// ```
+/// let resp = send_and_parse(&card, apdus::create_apdu_personal_information()).unwrap();
+/// let tlv = &tlvs::read_list(resp.get_data(), true)[0];
+/// let data = cardholder::CardholderData::from_tlv(tlv);
+/// println!("{} {}", data.given_names, data.surname);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardholderData {
+    /// Surname, with spaces in place of the `<` fillers.
+    pub surname: String,
+    /// Given names separated by spaces, can be empty.
+    pub given_names: String,
+    /// Language preferences (5F2D), each one is a two letter ISO 639-1 code.
+    pub languages: Vec<String>,
+    /// Salutation (5F35).
+    pub salutation: Salutation,
+}
+
+impl CardholderData {
+    /// Creates a new `CardholderData` without any language preference.
+    pub fn new(surname: &str, given_names: &str, salutation: Salutation) -> Self {
+        CardholderData {
+            surname: surname.to_string(),
+            given_names: given_names.to_string(),
+            languages: Vec::new(),
+            salutation,
+        }
+    }
+
+    /// Creates the `CardholderData` from the TLV returned for
+    /// [create_apdu_personal_information](../apdus/fn.create_apdu_personal_information.html).
+    pub fn from_tlv(tlv: &TLV) -> Self {
+        let (surname, given_names) = match tlv.get_name() {
+            Some(name) => parse_name(&name),
+            None => (String::new(), String::new()),
+        };
+        let languages = match tlv.find_tag(0x5F2D) {
+            Some(t) => parse_languages(&t.v),
+            None => Vec::new(),
+        };
+        let salutation = match tlv.find_tag(0x5F35) {
+            Some(t) if !t.v.is_empty() => Salutation::from_byte(t.v[0]),
+            _ => Salutation::NotAnnounced,
+        };
+        CardholderData {
+            surname,
+            given_names,
+            languages,
+            salutation,
+        }
+    }
+
+    /// Returns the name (5B) encoded according to ISO/IEC 7501-1.
+    pub fn encode_name(&self) -> Result<Vec<u8>, TalktoSCError> {
+        encode_name(&self.surname, &self.given_names)
+    }
+
+    /// Returns the language preferences (5F2D) as stored in the card.
+    pub fn encode_languages(&self) -> Result<Vec<u8>, TalktoSCError> {
+        encode_languages(&self.languages)
+    }
+
+    /// Returns the PUT DATA APDUs for the name, the language preferences and the salutation.
+    ///
+    /// The card requires PW3 to be verified before these APDUs.
+    pub fn create_apdus(&self) -> Result<Vec<APDU>, TalktoSCError> {
+        Ok(vec![
+            apdus::create_apdu_put_name(self.encode_name()?),
+            apdus::create_apdu_put_language(self.encode_languages()?),
+            apdus::create_apdu_put_salutation(self.salutation),
+        ])
+    }
+}
+
+/// Parses the ISO/IEC 7501-1 name into (surname, given names).
+///
+/// If there is no `<<` separator, then the whole name is the surname.
+pub fn parse_name(data: &[u8]) -> (String, String) {
+    let name = String::from_utf8_lossy(data);
+    let (surname, given_names) = match name.find("<<") {
+        Some(index) => (&name[..index], &name[index + 2..]),
+        None => (&name[..], ""),
+    };
+    (fillers_to_spaces(surname), fillers_to_spaces(given_names))
+}
+
+/// Encodes the surname and the given names according to ISO/IEC 7501-1.
+pub fn encode_name(surname: &str, given_names: &str) -> Result<Vec<u8>, TalktoSCError> {
+    if surname.contains('<') || given_names.contains('<') {
+        return Err(TalktoSCError::InvalidDataError(String::from(
+            "Name can not contain the '<' filler.",
+        )));
+    }
+    let mut name = spaces_to_fillers(surname);
+    let given_names = spaces_to_fillers(given_names);
+    if !given_names.is_empty() {
+        name.push_str("<<");
+        name.push_str(&given_names);
+    }
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "Name is longer than {} bytes.",
+            MAX_NAME_LENGTH
+        )));
+    }
+    Ok(name.into_bytes())
+}
+
+/// Parses the language preferences (5F2D) into a list of two letter codes.
+pub fn parse_languages(data: &[u8]) -> Vec<String> {
+    data.chunks_exact(2)
+        .map(|code| String::from_utf8_lossy(code).to_string())
+        .collect()
+}
+
+/// Encodes the list of language preferences, maximum 4 languages of two ASCII letters each.
+pub fn encode_languages(languages: &[String]) -> Result<Vec<u8>, TalktoSCError> {
+    if languages.len() > 4 {
+        return Err(TalktoSCError::InvalidDataError(String::from(
+            "Maximum 4 language preferences are allowed.",
+        )));
+    }
+    let mut res = Vec::new();
+    for language in languages {
+        if language.len() != 2 || !language.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return Err(TalktoSCError::InvalidDataError(format!(
+                "Invalid language code: {}",
+                language
+            )));
+        }
+        res.extend(language.to_ascii_lowercase().bytes());
+    }
+    Ok(res)
+}
+
+/// Replaces the `<` fillers with spaces.
+fn fillers_to_spaces(value: &str) -> String {
+    value
+        .split('<')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Replaces the spaces with `<` fillers.
+fn spaces_to_fillers(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<&str>>().join("<")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tlvs::read_list;
+    use std::fs::File;
+    use std::io::Read;
+
+    #[test]
+    fn test_parse_cardholder_data() {
+        let mut f = File::open("./data/name.binary").expect("no file found");
+        let mut buffer: Vec<u8> = Vec::new();
+        f.read_to_end(&mut buffer).unwrap();
+        let tlv = &read_list(buffer, true)[0];
+        let data = CardholderData::from_tlv(tlv);
+        assert_eq!(data.surname, "Das");
+        assert_eq!(data.given_names, "Kushal");
+        assert_eq!(data.languages, vec![String::from("en")]);
+        assert_eq!(data.salutation, Salutation::Mr);
+        assert_eq!(data.encode_name().unwrap(), b"Das<<Kushal".to_vec());
+        assert_eq!(data.encode_languages().unwrap(), b"en".to_vec());
+    }
+
+    #[test]
+    fn test_name_fillers() {
+        assert_eq!(
+            parse_name(b"van<der<Berg<<Anna<Maria"),
+            (String::from("van der Berg"), String::from("Anna Maria"))
+        );
+        assert_eq!(parse_name(b"Das"), (String::from("Das"), String::new()));
+        assert_eq!(
+            encode_name("van der Berg", "Anna  Maria").unwrap(),
+            b"van<der<Berg<<Anna<Maria".to_vec()
+        );
+        assert_eq!(encode_name("Das", "").unwrap(), b"Das".to_vec());
+        assert!(encode_name("Das<", "Kushal").is_err());
+        assert!(encode_name("A very long surname for the card", "and given names").is_err());
+    }
+
+    #[test]
+    fn test_cardholder_apdus() {
+        let mut data = CardholderData::new("Das", "Kushal", Salutation::Mr);
+        data.languages = vec![String::from("en"), String::from("SV")];
+        let apdus = data.create_apdus().unwrap();
+        assert_eq!(apdus[0].iapdus[0][..5], [0x00, 0xDA, 0x00, 0x5B, 11]);
+        assert_eq!(
+            apdus[1].iapdus[0],
+            vec![0x00, 0xDA, 0x5F, 0x2D, 4, b'e', b'n', b's', b'v']
+        );
+        assert_eq!(apdus[2].iapdus[0], vec![0x00, 0xDA, 0x5F, 0x35, 1, 0x31]);
+        data.languages.push(String::from("english"));
+        assert!(data.create_apdus().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! Module to read, write and delete the cardholder certificates (7F21) of the card.
+//!
+//! OpenPGP cards following the SPEC 3.x have one certificate for each of the signature,
+//! decryption and authentication keys. The certificate of a key slot is chosen via SELECT DATA
+//! before the GET DATA or PUT DATA. Read section 7.2.5 SELECT DATA of the
+//! [OpenPGP-smart-card-application-3.4.1.pdf](https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf).
+
+use crate::apdus;
+use crate::errors::TalktoSCError;
+use crate::pins::{self, PinReference};
+use crate::tlvs::{self, ExtendedCapabilities, ExtendedLengthInformation, KeySlot};
+use crate::CardTransport;
+
+/// Tag of the cardholder certificate DO.
+pub const CARDHOLDER_CERTIFICATE_TAG: u16 = 0x7F21;
+
+/// Returns the occurrence of the cardholder certificate DO for the key slot. The SPEC keeps the
+/// authentication certificate first, then decryption and signature.
+pub fn certificate_occurrence(slot: KeySlot) -> Result<u8, TalktoSCError> {
+    match slot {
+        KeySlot::Authentication => Ok(0x00),
+        KeySlot::Decryption => Ok(0x01),
+        KeySlot::Signature => Ok(0x02),
+        KeySlot::Attestation => Err(TalktoSCError::InvalidDataError(String::from(
+            "The attestation key does not have a cardholder certificate",
+        ))),
+    }
+}
+
+/// Selects the cardholder certificate of the key slot for the next GET DATA or PUT DATA.
+pub fn select_certificate(card: &impl CardTransport, slot: KeySlot) -> Result<(), TalktoSCError> {
+    let occurrence = certificate_occurrence(slot)?;
+    crate::send_and_parse(
+        card,
+        apdus::create_apdu_select_data(occurrence, CARDHOLDER_CERTIFICATE_TAG),
+    )?
+    .check()?;
+    Ok(())
+}
+
+/// Reads the cardholder certificate of the key slot, `None` if there is no certificate.
+///
+/// # Example
+///
+/// ```
+/// let cert = certificates::read_certificate(&card, tlvs::KeySlot::Signature).unwrap();
+/// ```
+pub fn read_certificate(
+    card: &impl CardTransport,
+    slot: KeySlot,
+) -> Result<Option<Vec<u8>>, TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    let extended_length = ExtendedLengthInformation::from_tlv(&tlv).is_some();
+    select_certificate(card, slot)?;
+    let resp = crate::send_and_read_all(
+        card,
+        apdus::create_apdu_get_cardholder_certificate(extended_length),
+    )?
+    .check()?;
+    let data = resp.get_data();
+    if data.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(data))
+}
+
+/// Verifies PW3 and writes the certificate (say a DER encoded X.509 certificate) for the key slot.
+///
+/// The certificate can not be longer than the maximum certificate length in the Extended
+/// capabilities.
+pub fn write_certificate(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    slot: KeySlot,
+    certificate: Vec<u8>,
+) -> Result<(), TalktoSCError> {
+    if certificate.is_empty() {
+        return delete_certificate(card, admin_pin, slot);
+    }
+    let tlv = crate::get_application_data(card)?;
+    let caps = ExtendedCapabilities::from_tlv(&tlv).ok_or(TalktoSCError::MissingTagError(0xC0))?;
+    if certificate.len() > caps.max_certificate_length as usize {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "Maximum certificate length is {} bytes",
+            caps.max_certificate_length
+        )));
+    }
+    let extended_length = tlvs::use_extended_length(&tlv, certificate.len());
+    pins::verify_pin(card, PinReference::Pw3, admin_pin)?;
+    select_certificate(card, slot)?;
+    crate::send_and_parse(
+        card,
+        apdus::create_apdu_put_cardholder_certificate(certificate, extended_length),
+    )?
+    .check()?;
+    Ok(())
+}
+
+/// Verifies PW3 and deletes the certificate of the key slot.
+pub fn delete_certificate(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    slot: KeySlot,
+) -> Result<(), TalktoSCError> {
+    pins::verify_pin(card, PinReference::Pw3, admin_pin)?;
+    select_certificate(card, slot)?;
+    crate::send_and_parse(card, apdus::create_apdu_delete_cardholder_certificate())?.check()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_data_apdu() {
+        let occurrence = certificate_occurrence(KeySlot::Decryption).unwrap();
+        let apdu = apdus::create_apdu_select_data(occurrence, CARDHOLDER_CERTIFICATE_TAG);
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![
+                0x00, 0xA5, 0x01, 0x04, 0x06, 0x60, 0x04, 0x5C, 0x02, 0x7F, 0x21
+            ]]
+        );
+        assert!(certificate_occurrence(KeySlot::Attestation).is_err());
+    }
+
+    #[test]
+    fn test_cardholder_certificate_apdus() {
+        let apdu = apdus::create_apdu_get_cardholder_certificate(false);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xCA, 0x7F, 0x21, 0x00]]);
+        let apdu = apdus::create_apdu_get_cardholder_certificate(true);
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![0x00, 0xCA, 0x7F, 0x21, 0x00, 0x00, 0x00]]
+        );
+
+        let apdu = apdus::create_apdu_put_cardholder_certificate(vec![0x30; 600], true);
+        assert_eq!(apdu.iapdus.len(), 1);
+        assert_eq!(
+            apdu.iapdus[0][..7],
+            [0x00, 0xDA, 0x7F, 0x21, 0x00, 0x02, 0x58]
+        );
+        let apdu = apdus::create_apdu_put_cardholder_certificate(vec![0x30; 600], false);
+        assert_eq!(apdu.iapdus.len(), 3);
+        assert_eq!(apdu.iapdus[0][..5], [0x10, 0xDA, 0x7F, 0x21, 254]);
+        assert_eq!(apdu.iapdus[2][..5], [0x00, 0xDA, 0x7F, 0x21, 92]);
+
+        let apdu = apdus::create_apdu_delete_cardholder_certificate();
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xDA, 0x7F, 0x21]]);
+    }
+}
@@ -0,0 +1,702 @@
+//! Module for the private key operations of the card.
+//!
+//! The functions here format the input for the algorithm of the key slot, before sending it to
+//! the card.
+
+use crate::algorithms::{AlgorithmAttributes, Curve, EccAlgorithm};
+use crate::apdus;
+use crate::errors::TalktoSCError;
+use crate::pins::{self, PinReference};
+use crate::tlvs::{self, ExtendedCapabilities, KeySlot};
+use crate::uif;
+use crate::CardTransport;
+
+/// Hash algorithms supported for the RSA DigestInfo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Returns the size of the digest in bytes.
+    pub fn digest_size(self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => 20,
+            HashAlgorithm::Sha224 => 28,
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha384 => 48,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+
+    /// Returns the hash algorithm id of OpenPGP (RFC 4880 section 9.4).
+    pub fn openpgp_id(self) -> u8 {
+        match self {
+            HashAlgorithm::Sha1 => 0x02,
+            HashAlgorithm::Sha224 => 0x0B,
+            HashAlgorithm::Sha256 => 0x08,
+            HashAlgorithm::Sha384 => 0x09,
+            HashAlgorithm::Sha512 => 0x0A,
+        }
+    }
+
+    /// Returns the hash algorithm for the OpenPGP hash algorithm id.
+    pub fn from_openpgp_id(id: u8) -> Option<Self> {
+        match id {
+            0x02 => Some(HashAlgorithm::Sha1),
+            0x0B => Some(HashAlgorithm::Sha224),
+            0x08 => Some(HashAlgorithm::Sha256),
+            0x09 => Some(HashAlgorithm::Sha384),
+            0x0A => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Returns the DER encoded DigestInfo header of PKCS#1, to be followed by the digest.
+    pub fn digest_info_prefix(self) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha1 => vec![
+                0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2B, 0x0E, 0x03, 0x02, 0x1A, 0x05, 0x00, 0x04,
+                0x14,
+            ],
+            HashAlgorithm::Sha224 => sha2_prefix(0x2D, 0x04, 0x1C),
+            HashAlgorithm::Sha256 => sha2_prefix(0x31, 0x01, 0x20),
+            HashAlgorithm::Sha384 => sha2_prefix(0x41, 0x02, 0x30),
+            HashAlgorithm::Sha512 => sha2_prefix(0x51, 0x03, 0x40),
+        }
+    }
+}
+
+/// The SHA2 family DigestInfo headers only differ in the lengths and the last byte of the OID.
+fn sha2_prefix(length: u8, oid: u8, size: u8) -> Vec<u8> {
+    vec![
+        0x30, length, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, oid,
+        0x05, 0x00, 0x04, size,
+    ]
+}
+
+/// Returns the algorithm attributes of the slot from the Application related data (6E).
+fn get_algorithm_attributes(
+    tlv: &tlvs::TLV,
+    slot: KeySlot,
+) -> Result<AlgorithmAttributes, TalktoSCError> {
+    AlgorithmAttributes::from_tlv(tlv, slot)
+        .ok_or_else(|| TalktoSCError::MissingTagError(slot.algo_attributes_tag()))
+}
+
+/// Creates the PKCS#1 DigestInfo for the given digest.
+pub fn create_digest_info(hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>, TalktoSCError> {
+    check_digest(hash, digest)?;
+    let mut res = hash.digest_info_prefix();
+    res.extend(digest.iter());
+    Ok(res)
+}
+
+/// Checks that the digest has the right size for the hash algorithm.
+fn check_digest(hash: HashAlgorithm, digest: &[u8]) -> Result<(), TalktoSCError> {
+    if digest.len() != hash.digest_size() {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "Digest of {} bytes does not match {:?}",
+            digest.len(),
+            hash
+        )));
+    }
+    Ok(())
+}
+
+/// The SPEC allows the RSA signature input to be at most 40% of the modulus length.
+fn check_rsa_input(modulus_bits: u16, data: &[u8]) -> Result<(), TalktoSCError> {
+    let max_length = (modulus_bits as usize / 8) * 40 / 100;
+    if data.len() > max_length {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "Input of {} bytes is too long for RSA {}",
+            data.len(),
+            modulus_bits
+        )));
+    }
+    Ok(())
+}
+
+/// Formats the digest as the input of the PSO: COMPUTE DIGITAL SIGNATURE (or INTERNAL
+/// AUTHENTICATE) for the given algorithm attributes.
+///
+/// RSA keys get the PKCS#1 DigestInfo, which can not be more than 40% of the modulus length.
+/// ECDSA and EdDSA keys get the digest as it is.
+pub fn create_signature_input(
+    algo: &AlgorithmAttributes,
+    hash: HashAlgorithm,
+    digest: &[u8],
+) -> Result<Vec<u8>, TalktoSCError> {
+    match algo {
+        AlgorithmAttributes::Rsa { modulus_bits, .. } => {
+            let data = create_digest_info(hash, digest)?;
+            check_rsa_input(*modulus_bits, &data)?;
+            Ok(data)
+        }
+        AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::Ecdsa,
+            ..
+        }
+        | AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::EdDsa,
+            ..
+        } => {
+            check_digest(hash, digest)?;
+            Ok(digest.to_vec())
+        }
+        _ => Err(TalktoSCError::InvalidDataError(format!(
+            "Can not sign with the algorithm {:?}",
+            algo
+        ))),
+    }
+}
+
+/// Verifies PW1 for signing, and signs the digest with the signature key of the card. Returns the
+/// signature bytes.
+///
+/// The digest is formatted according to the algorithm attributes of the signature slot, see
+/// [create_signature_input].
+///
+/// # Example
+///
+/// ```
+/// let signature = crypto::sign(&card, pin, crypto::HashAlgorithm::Sha256, &digest).unwrap();
+/// ```
+pub fn sign(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    hash: HashAlgorithm,
+    digest: &[u8],
+) -> Result<Vec<u8>, TalktoSCError> {
+    sign_with_touch(card, pin, hash, digest, || ())
+}
+
+/// Same as [sign], but calls `waiting_for_touch` before sending the digest if the touch policy of
+/// the signature key is on, as the card then waits till the user touches it.
+pub fn sign_with_touch<F: FnMut()>(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    hash: HashAlgorithm,
+    digest: &[u8],
+    mut waiting_for_touch: F,
+) -> Result<Vec<u8>, TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    let algo = get_algorithm_attributes(&tlv, KeySlot::Signature)?;
+    let data = create_signature_input(&algo, hash, digest)?;
+    pins::verify_pin(card, PinReference::Pw1ForSign, pin)?;
+    if uif::requires_touch(&tlv, KeySlot::Signature) {
+        waiting_for_touch();
+    }
+    let resp = crate::send_and_read_all(card, apdus::create_apdu_for_signing(data))?.check()?;
+    Ok(resp.get_data())
+}
+
+/// Formats the input of the INTERNAL AUTHENTICATE operation for the given algorithm attributes.
+///
+/// With a hash algorithm, the data is a digest and gets formatted like
+/// [create_signature_input]. Without any hash algorithm, the data is the raw challenge (say
+/// for EdDSA based SSH authentication), which for RSA keys can not be more than 40% of the
+/// modulus length.
+pub fn create_authentication_input(
+    algo: &AlgorithmAttributes,
+    hash: Option<HashAlgorithm>,
+    data: &[u8],
+) -> Result<Vec<u8>, TalktoSCError> {
+    if let Some(hash) = hash {
+        return create_signature_input(algo, hash, data);
+    }
+    match algo {
+        AlgorithmAttributes::Rsa { modulus_bits, .. } => {
+            check_rsa_input(*modulus_bits, data)?;
+            Ok(data.to_vec())
+        }
+        AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::Ecdsa,
+            ..
+        }
+        | AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::EdDsa,
+            ..
+        } => Ok(data.to_vec()),
+        _ => Err(TalktoSCError::InvalidDataError(format!(
+            "Can not authenticate with the algorithm {:?}",
+            algo
+        ))),
+    }
+}
+
+/// Verifies PW1 for other operations, and signs the challenge (or digest) with the
+/// authentication key of the card via INTERNAL AUTHENTICATE. Returns the signature bytes.
+///
+/// The data is formatted according to the algorithm attributes of the authentication slot, see
+/// [create_authentication_input].
+///
+/// # Example
+///
+/// ```
+/// let signature = crypto::authenticate(&card, pin, None, &challenge).unwrap();
+/// ```
+pub fn authenticate(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    hash: Option<HashAlgorithm>,
+    data: &[u8],
+) -> Result<Vec<u8>, TalktoSCError> {
+    authenticate_with_touch(card, pin, hash, data, || ())
+}
+
+/// Same as [authenticate], but calls `waiting_for_touch` before sending the data if the touch
+/// policy of the authentication key is on.
+pub fn authenticate_with_touch<F: FnMut()>(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    hash: Option<HashAlgorithm>,
+    data: &[u8],
+    mut waiting_for_touch: F,
+) -> Result<Vec<u8>, TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    let algo = get_algorithm_attributes(&tlv, KeySlot::Authentication)?;
+    let data = create_authentication_input(&algo, hash, data)?;
+    pins::verify_pin(card, PinReference::Pw1ForOthers, pin)?;
+    if uif::requires_touch(&tlv, KeySlot::Authentication) {
+        waiting_for_touch();
+    }
+    let resp =
+        crate::send_and_read_all(card, apdus::create_apdu_internal_authenticate(data))?.check()?;
+    Ok(resp.get_data())
+}
+
+/// Input for the PSO: DECIPHER operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecipherInput {
+    /// The RSA encrypted session key.
+    Rsa(Vec<u8>),
+    /// The ephemeral ECC public key (point) of the sender. X25519 points can have the OpenPGP
+    /// `0x40` prefix.
+    Ecc(Vec<u8>),
+}
+
+/// Formats the input of the PSO: DECIPHER operation for the given algorithm attributes.
+///
+/// RSA cryptograms get the `0x00` padding indicator byte. ECC points are wrapped in the cipher DO
+/// `A6 { 7F49 { 86 <point> } }`, X25519 points without the `0x40` prefix.
+pub fn create_decipher_input(
+    algo: &AlgorithmAttributes,
+    input: &DecipherInput,
+) -> Result<Vec<u8>, TalktoSCError> {
+    match (algo, input) {
+        (AlgorithmAttributes::Rsa { .. }, DecipherInput::Rsa(cryptogram)) => {
+            let mut res = vec![0x00];
+            res.extend(cryptogram.iter());
+            Ok(res)
+        }
+        (
+            AlgorithmAttributes::Ecc {
+                algorithm: EccAlgorithm::Ecdh,
+                curve,
+                ..
+            },
+            DecipherInput::Ecc(point),
+        ) => {
+            let point = match curve {
+                Curve::Cv25519 if point.len() == 33 && point[0] == 0x40 => &point[1..],
+                _ => &point[..],
+            };
+            if let Curve::Cv25519 = curve {
+                if point.len() != 32 {
+                    return Err(TalktoSCError::InvalidDataError(String::from(
+                        "X25519 point must be 32 bytes",
+                    )));
+                }
+            }
+            let public_key = tlvs::encode_tlv(0x7F49, &tlvs::encode_tlv(0x86, point));
+            Ok(tlvs::encode_tlv(0xA6, &public_key))
+        }
+        _ => Err(TalktoSCError::InvalidDataError(format!(
+            "Can not decipher {:?} input with the algorithm {:?}",
+            input, algo
+        ))),
+    }
+}
+
+/// Verifies PW1 for other operations, and deciphers the input with the decryption key of the card.
+/// Returns the session key for RSA, or the shared secret for ECDH.
+///
+/// The input is formatted according to the algorithm attributes of the decryption slot, see
+/// [create_decipher_input].
+///
+/// # Example
+///
+/// ```
+/// let input = crypto::DecipherInput::Ecc(ephemeral_key);
+/// let shared_secret = crypto::decipher(&card, pin, &input).unwrap();
+/// ```
+pub fn decipher(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    input: &DecipherInput,
+) -> Result<Vec<u8>, TalktoSCError> {
+    decipher_with_touch(card, pin, input, || ())
+}
+
+/// Same as [decipher], but calls `waiting_for_touch` before sending the input if the touch policy
+/// of the decryption key is on.
+pub fn decipher_with_touch<F: FnMut()>(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    input: &DecipherInput,
+    mut waiting_for_touch: F,
+) -> Result<Vec<u8>, TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    let algo = get_algorithm_attributes(&tlv, KeySlot::Decryption)?;
+    let data = create_decipher_input(&algo, input)?;
+    pins::verify_pin(card, PinReference::Pw1ForOthers, pin)?;
+    if uif::requires_touch(&tlv, KeySlot::Decryption) {
+        waiting_for_touch();
+    }
+    let resp = crate::send_and_read_all(card, apdus::create_apdu_for_decryption(data))?.check()?;
+    Ok(resp.get_data())
+}
+
+/// Size of the AES blocks.
+pub const AES_BLOCK_SIZE: usize = 16;
+
+/// Checks the AES capability of the card, and that the data is made of whole AES blocks.
+fn check_aes_input(tlv: &tlvs::TLV, data: &[u8]) -> Result<(), TalktoSCError> {
+    if data.is_empty() || !data.len().is_multiple_of(AES_BLOCK_SIZE) {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "AES data must be a multiple of {} bytes",
+            AES_BLOCK_SIZE
+        )));
+    }
+    let caps = ExtendedCapabilities::from_tlv(tlv).ok_or(TalktoSCError::MissingTagError(0xC0))?;
+    if !caps.aes {
+        return Err(TalktoSCError::NotSupportedError(String::from("AES")));
+    }
+    Ok(())
+}
+
+/// Verifies PW1 for other operations, and encrypts the data with the AES key of the card. The
+/// card does not pad, so the data must be a multiple of [AES_BLOCK_SIZE].
+///
+/// # Example
+///
+/// ```
+/// let cryptogram = crypto::encipher_aes(&card, pin, &disk_key).unwrap();
+/// ```
+pub fn encipher_aes(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    data: &[u8],
+) -> Result<Vec<u8>, TalktoSCError> {
+    encipher_aes_with_touch(card, pin, data, || ())
+}
+
+/// Same as [encipher_aes], but calls `waiting_for_touch` before sending the data if the touch
+/// policy of the decryption key (D7) is on, the card uses it for the AES key too.
+pub fn encipher_aes_with_touch<F: FnMut()>(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    data: &[u8],
+    mut waiting_for_touch: F,
+) -> Result<Vec<u8>, TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    check_aes_input(&tlv, data)?;
+    pins::verify_pin(card, PinReference::Pw1ForOthers, pin)?;
+    if uif::requires_touch(&tlv, KeySlot::Decryption) {
+        waiting_for_touch();
+    }
+    let resp =
+        crate::send_and_read_all(card, apdus::create_apdu_aes_encipher(data.to_vec()))?.check()?;
+    let data = resp.get_data();
+    // Skip the padding indicator
+    match data.split_first() {
+        Some((0x02, cryptogram)) => Ok(cryptogram.to_vec()),
+        _ => Err(TalktoSCError::InvalidDataError(String::from(
+            "Missing the AES padding indicator in the response",
+        ))),
+    }
+}
+
+/// Verifies PW1 for other operations, and decrypts the cryptogram from [encipher_aes] with the
+/// AES key of the card.
+pub fn decipher_aes(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    cryptogram: &[u8],
+) -> Result<Vec<u8>, TalktoSCError> {
+    decipher_aes_with_touch(card, pin, cryptogram, || ())
+}
+
+/// Same as [decipher_aes], but calls `waiting_for_touch` before sending the cryptogram if the
+/// touch policy of the decryption key (D7) is on.
+pub fn decipher_aes_with_touch<F: FnMut()>(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    cryptogram: &[u8],
+    mut waiting_for_touch: F,
+) -> Result<Vec<u8>, TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    check_aes_input(&tlv, cryptogram)?;
+    pins::verify_pin(card, PinReference::Pw1ForOthers, pin)?;
+    if uif::requires_touch(&tlv, KeySlot::Decryption) {
+        waiting_for_touch();
+    }
+    let resp =
+        crate::send_and_read_all(card, apdus::create_apdu_aes_decipher(cryptogram.to_vec()))?
+            .check()?;
+    Ok(resp.get_data())
+}
+
+/// Selects the key used for the PSO: DECIPHER (`operation` [KeySlot::Decryption]) or the
+/// INTERNAL AUTHENTICATE (`operation` [KeySlot::Authentication]) operation via MANAGE SECURITY
+/// ENVIRONMENT.
+///
+/// The selection is not persistent, the card goes back to the default keys on reset or on
+/// SELECT of the application.
+pub fn manage_security_environment(
+    card: &impl CardTransport,
+    operation: KeySlot,
+    key: KeySlot,
+) -> Result<(), TalktoSCError> {
+    for slot in [operation, key].iter() {
+        match slot {
+            KeySlot::Decryption | KeySlot::Authentication => (),
+            _ => {
+                return Err(TalktoSCError::InvalidDataError(format!(
+                    "MANAGE SECURITY ENVIRONMENT does not work with the {:?} key",
+                    slot
+                )))
+            }
+        }
+    }
+    let caps = crate::get_extended_capabilities(card)?;
+    if !caps.manage_security_environment {
+        return Err(TalktoSCError::NotSupportedError(String::from(
+            "MANAGE SECURITY ENVIRONMENT",
+        )));
+    }
+    crate::send_and_parse(
+        card,
+        apdus::create_apdu_manage_security_environment(operation, key),
+    )?
+    .check()?;
+    Ok(())
+}
+
+/// Redirects PSO: DECIPHER to the authentication key of the card.
+pub fn use_authentication_key_for_decryption(
+    card: &impl CardTransport,
+) -> Result<(), TalktoSCError> {
+    manage_security_environment(card, KeySlot::Decryption, KeySlot::Authentication)
+}
+
+/// Sets PSO: DECIPHER back to the decryption key of the card.
+pub fn restore_decryption_key(card: &impl CardTransport) -> Result<(), TalktoSCError> {
+    manage_security_environment(card, KeySlot::Decryption, KeySlot::Decryption)
+}
+
+/// Verifies PW1 for other operations, and deciphers the input with the authentication key of the
+/// card. The decryption key is selected again afterwards, even if the operation failed.
+///
+/// The input is formatted like [decipher] does, based on the curve of the authentication key, so
+/// an ECDSA key in the authentication slot can be used for ECDH.
+pub fn decipher_with_authentication_key(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    input: &DecipherInput,
+) -> Result<Vec<u8>, TalktoSCError> {
+    decipher_with_authentication_key_with_touch(card, pin, input, || ())
+}
+
+/// Same as [decipher_with_authentication_key], but calls `waiting_for_touch` before sending the
+/// input if the touch policy of the authentication key (D8) is on.
+pub fn decipher_with_authentication_key_with_touch<F: FnMut()>(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    input: &DecipherInput,
+    mut waiting_for_touch: F,
+) -> Result<Vec<u8>, TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    let algo = match get_algorithm_attributes(&tlv, KeySlot::Authentication)? {
+        AlgorithmAttributes::Ecc {
+            curve,
+            import_with_public_key,
+            ..
+        } => AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::Ecdh,
+            curve,
+            import_with_public_key,
+        },
+        algo => algo,
+    };
+    let data = create_decipher_input(&algo, input)?;
+    pins::verify_pin(card, PinReference::Pw1ForOthers, pin)?;
+    use_authentication_key_for_decryption(card)?;
+    if uif::requires_touch(&tlv, KeySlot::Authentication) {
+        waiting_for_touch();
+    }
+    let resp = crate::send_and_read_all(card, apdus::create_apdu_for_decryption(data))
+        .and_then(|resp| resp.check());
+    restore_decryption_key(card)?;
+    Ok(resp?.get_data())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{Curve, RsaImportFormat};
+
+    fn rsa(modulus_bits: u16) -> AlgorithmAttributes {
+        AlgorithmAttributes::Rsa {
+            modulus_bits,
+            exponent_bits: 17,
+            import_format: RsaImportFormat::Standard,
+        }
+    }
+
+    #[test]
+    fn test_digest_info() {
+        let digest = vec![0xAB; 32];
+        let data = create_digest_info(HashAlgorithm::Sha256, &digest).unwrap();
+        assert_eq!(data.len(), 51);
+        assert_eq!(
+            data[..19],
+            [
+                0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+                0x01, 0x05, 0x00, 0x04, 0x20
+            ]
+        );
+        assert_eq!(data[19..], digest[..]);
+        for hash in [
+            HashAlgorithm::Sha1,
+            HashAlgorithm::Sha224,
+            HashAlgorithm::Sha384,
+            HashAlgorithm::Sha512,
+        ] {
+            let prefix = hash.digest_info_prefix();
+            // The outer SEQUENCE length covers everything after the first two bytes.
+            assert_eq!(prefix[1] as usize, prefix.len() - 2 + hash.digest_size());
+        }
+        assert!(create_digest_info(HashAlgorithm::Sha512, &digest).is_err());
+    }
+
+    #[test]
+    fn test_signature_input() {
+        let digest = vec![0x01; 64];
+        let data = create_signature_input(&rsa(4096), HashAlgorithm::Sha512, &digest).unwrap();
+        assert_eq!(data.len(), 83);
+        // 83 bytes are more than 40% of the 128 bytes RSA 1024 modulus.
+        assert!(create_signature_input(&rsa(1024), HashAlgorithm::Sha512, &digest).is_err());
+
+        let eddsa = AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::EdDsa,
+            curve: Curve::Ed25519,
+            import_with_public_key: false,
+        };
+        let data = create_signature_input(&eddsa, HashAlgorithm::Sha512, &digest).unwrap();
+        assert_eq!(data, digest);
+
+        let ecdh = AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::Ecdh,
+            curve: Curve::Cv25519,
+            import_with_public_key: false,
+        };
+        assert!(create_signature_input(&ecdh, HashAlgorithm::Sha512, &digest).is_err());
+    }
+
+    #[test]
+    fn test_authentication_input() {
+        let digest = vec![0x01; 32];
+        let data =
+            create_authentication_input(&rsa(2048), Some(HashAlgorithm::Sha256), &digest).unwrap();
+        assert_eq!(
+            data,
+            create_digest_info(HashAlgorithm::Sha256, &digest).unwrap()
+        );
+        assert_eq!(
+            create_authentication_input(&rsa(2048), None, &digest).unwrap(),
+            digest
+        );
+        assert!(create_authentication_input(&rsa(2048), None, &[0x01; 103]).is_err());
+
+        let eddsa = AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::EdDsa,
+            curve: Curve::Ed25519,
+            import_with_public_key: false,
+        };
+        let challenge = vec![0x02; 150];
+        assert_eq!(
+            create_authentication_input(&eddsa, None, &challenge).unwrap(),
+            challenge
+        );
+    }
+
+    #[test]
+    fn test_decipher_input() {
+        let data = create_decipher_input(&rsa(2048), &DecipherInput::Rsa(vec![0xAA; 256])).unwrap();
+        assert_eq!(data.len(), 257);
+        assert_eq!(data[0], 0x00);
+
+        let ecdh = AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::Ecdh,
+            curve: Curve::Cv25519,
+            import_with_public_key: false,
+        };
+        let mut point = vec![0x40];
+        point.extend(vec![0xBB; 32]);
+        let data = create_decipher_input(&ecdh, &DecipherInput::Ecc(point)).unwrap();
+        let mut expected = vec![0xA6, 0x25, 0x7F, 0x49, 0x22, 0x86, 0x20];
+        expected.extend(vec![0xBB; 32]);
+        assert_eq!(data, expected);
+        assert_eq!(
+            create_decipher_input(&ecdh, &DecipherInput::Ecc(vec![0xBB; 32])).unwrap(),
+            expected
+        );
+        assert!(create_decipher_input(&ecdh, &DecipherInput::Ecc(vec![0xBB; 31])).is_err());
+        assert!(create_decipher_input(&ecdh, &DecipherInput::Rsa(vec![0xAA; 256])).is_err());
+
+        let p256 = AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::Ecdh,
+            curve: Curve::NistP256,
+            import_with_public_key: false,
+        };
+        let data = create_decipher_input(&p256, &DecipherInput::Ecc(vec![0x04; 65])).unwrap();
+        assert_eq!(data[..7], [0xA6, 0x46, 0x7F, 0x49, 0x43, 0x86, 0x41]);
+    }
+
+    #[test]
+    fn test_manage_security_environment_apdu() {
+        let apdu = apdus::create_apdu_manage_security_environment(
+            KeySlot::Decryption,
+            KeySlot::Authentication,
+        );
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![0x00, 0x22, 0x41, 0xB8, 0x03, 0x83, 0x01, 0x03]]
+        );
+        let apdu = apdus::create_apdu_manage_security_environment(
+            KeySlot::Authentication,
+            KeySlot::Decryption,
+        );
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![0x00, 0x22, 0x41, 0xA4, 0x03, 0x83, 0x01, 0x02]]
+        );
+    }
+
+    #[test]
+    fn test_aes_apdus() {
+        let apdu = apdus::create_apdu_aes_encipher(vec![0xAA; 16]);
+        assert_eq!(apdu.iapdus[0][..5], [0x00, 0x2A, 0x86, 0x80, 0x10]);
+        let apdu = apdus::create_apdu_aes_decipher(vec![0xBB; 16]);
+        assert_eq!(apdu.iapdus[0][..6], [0x00, 0x2A, 0x80, 0x86, 0x11, 0x02]);
+        assert_eq!(apdu.iapdus[0].len(), 22);
+        let apdu = apdus::create_apdu_put_aes_key(vec![0xCC; 32]);
+        assert_eq!(apdu.iapdus[0][..5], [0x00, 0xDA, 0x00, 0xD5, 0x20]);
+    }
+}
@@ -0,0 +1,450 @@
+//! Module for a generic PUT DATA over the writable DOs of the card.
+//!
+//! [DataObject] lists every DO that can be written with PUT DATA, with the access conditions and
+//! the maximum length of the value from the section 4.4.1 of the
+//! [OpenPGP-smart-card-application-3.4.1.pdf](https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf).
+//! [put_data] checks both before sending anything to the card. The pins are not verified here,
+//! use [pins::verify_pin](../pins/fn.verify_pin.html) first.
+//!
+//! [DataValue] has the typed values for the cardholder DOs, encoded the same way as in the
+//! [cardholder](../cardholder/index.html) module, see [put_value].
+
+use crate::apdus::{self, APDU};
+use crate::cardholder::{self, Salutation};
+use crate::errors::TalktoSCError;
+use crate::pins::{self, PinReference, VerificationStatus};
+use crate::tlvs::{self, ExtendedCapabilities, KeySlot, PasswordStatus};
+use crate::CardTransport;
+
+/// Access condition of a DO for GET DATA or PUT DATA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessCondition {
+    /// Free access.
+    Always,
+    /// The pin must be verified first.
+    Verify(PinReference),
+    /// Not possible at all.
+    Never,
+}
+
+/// Maximum length of the value of a DO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxLength {
+    /// Fixed by the SPEC.
+    Fixed(usize),
+    /// Maximum length of the special DOs in the Extended capabilities (C0).
+    SpecialDo,
+    /// Maximum length of the Cardholder certificates in the Extended capabilities (C0).
+    Certificate,
+    /// Maximum length of the Resetting Code in the PW status bytes (C4).
+    ResettingCode,
+    /// Not limited by the SPEC, the card decides.
+    Unlimited,
+}
+
+/// The DOs of the OpenPGP applet which can be written with PUT DATA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataObject {
+    /// Private use DO 0101, PW1 (0x82) to write.
+    PrivateUse1,
+    /// Private use DO 0102, PW3 to write.
+    PrivateUse2,
+    /// Private use DO 0103, PW1 (0x82) to read and write.
+    PrivateUse3,
+    /// Private use DO 0104, PW3 to read and write.
+    PrivateUse4,
+    /// Name (5B).
+    Name,
+    /// Login data (5E).
+    LoginData,
+    /// Language preference (5F2D).
+    Language,
+    /// Salutation (5F35).
+    Salutation,
+    /// URL of the public keys (5F50).
+    Url,
+    /// Cardholder certificate (7F21), of the occurrence chosen via SELECT DATA.
+    CardholderCertificate,
+    /// Algorithm attributes of the key slot (C1, C2, C3 or DA).
+    AlgorithmAttributes(KeySlot),
+    /// PW status bytes (C4), only the first byte can be changed.
+    PasswordStatus,
+    /// Fingerprint of the key in the slot (C7, C8, C9 or DB).
+    Fingerprint(KeySlot),
+    /// CA fingerprint of the slot (CA, CB, CC or DC).
+    CaFingerprint(KeySlot),
+    /// Generation time of the key in the slot (CE, CF, D0 or DD).
+    GenerationTime(KeySlot),
+    /// Secure messaging key for encryption (D1).
+    SmEncKey,
+    /// Secure messaging key for MAC (D2).
+    SmMacKey,
+    /// Resetting Code (D3).
+    ResettingCode,
+    /// AES key for PSO: DECIPHER and ENCIPHER (D5).
+    AesKey,
+    /// User Interaction Flag of the slot (D6, D7, D8 or D9).
+    Uif(KeySlot),
+    /// Container for both the secure messaging keys (F4).
+    SmKeys,
+    /// KDF-DO (F9).
+    Kdf,
+    /// Extended header list (4D) to import a private key, the only DO with the odd INS (DB).
+    ExtendedHeaderList,
+}
+
+impl DataObject {
+    /// Returns the tag of the DO, which is P1-P2 for the even PUT DATA.
+    pub fn tag(self) -> u16 {
+        match self {
+            DataObject::PrivateUse1 => 0x0101,
+            DataObject::PrivateUse2 => 0x0102,
+            DataObject::PrivateUse3 => 0x0103,
+            DataObject::PrivateUse4 => 0x0104,
+            DataObject::Name => 0x5B,
+            DataObject::LoginData => 0x5E,
+            DataObject::Language => 0x5F2D,
+            DataObject::Salutation => 0x5F35,
+            DataObject::Url => 0x5F50,
+            DataObject::CardholderCertificate => 0x7F21,
+            DataObject::AlgorithmAttributes(slot) => slot.algo_attributes_tag(),
+            DataObject::PasswordStatus => 0xC4,
+            DataObject::Fingerprint(slot) => slot.fingerprint_tag(),
+            DataObject::CaFingerprint(slot) => slot.ca_fingerprint_tag(),
+            DataObject::GenerationTime(slot) => slot.generation_timestamp_tag(),
+            DataObject::SmEncKey => 0xD1,
+            DataObject::SmMacKey => 0xD2,
+            DataObject::ResettingCode => 0xD3,
+            DataObject::AesKey => 0xD5,
+            DataObject::Uif(slot) => slot.uif_tag(),
+            DataObject::SmKeys => 0xF4,
+            DataObject::Kdf => 0xF9,
+            DataObject::ExtendedHeaderList => 0x4D,
+        }
+    }
+
+    /// Tells if the DO is written with the odd INS (DB), with P1-P2 3FFF and the whole TLV as
+    /// data.
+    pub fn uses_odd_ins(self) -> bool {
+        self == DataObject::ExtendedHeaderList
+    }
+
+    /// Returns the access condition for GET DATA.
+    pub fn read_access(self) -> AccessCondition {
+        match self {
+            DataObject::PrivateUse3 => AccessCondition::Verify(PinReference::Pw1ForOthers),
+            DataObject::PrivateUse4 => AccessCondition::Verify(PinReference::Pw3),
+            DataObject::SmEncKey
+            | DataObject::SmMacKey
+            | DataObject::ResettingCode
+            | DataObject::AesKey
+            | DataObject::SmKeys
+            | DataObject::ExtendedHeaderList => AccessCondition::Never,
+            _ => AccessCondition::Always,
+        }
+    }
+
+    /// Returns the access condition for PUT DATA.
+    pub fn write_access(self) -> AccessCondition {
+        match self {
+            DataObject::PrivateUse1 | DataObject::PrivateUse3 => {
+                AccessCondition::Verify(PinReference::Pw1ForOthers)
+            }
+            _ => AccessCondition::Verify(PinReference::Pw3),
+        }
+    }
+
+    /// Returns the maximum length of the value.
+    pub fn max_length(self) -> MaxLength {
+        match self {
+            DataObject::PrivateUse1
+            | DataObject::PrivateUse2
+            | DataObject::PrivateUse3
+            | DataObject::PrivateUse4
+            | DataObject::LoginData
+            | DataObject::Url
+            | DataObject::AlgorithmAttributes(_)
+            | DataObject::Kdf => MaxLength::SpecialDo,
+            DataObject::Name => MaxLength::Fixed(39),
+            DataObject::Language => MaxLength::Fixed(8),
+            DataObject::Salutation | DataObject::PasswordStatus => MaxLength::Fixed(1),
+            DataObject::CardholderCertificate => MaxLength::Certificate,
+            DataObject::Fingerprint(_) | DataObject::CaFingerprint(_) => MaxLength::Fixed(20),
+            DataObject::GenerationTime(_) => MaxLength::Fixed(4),
+            DataObject::SmEncKey | DataObject::SmMacKey | DataObject::AesKey => {
+                MaxLength::Fixed(32)
+            }
+            DataObject::ResettingCode => MaxLength::ResettingCode,
+            DataObject::Uif(_) => MaxLength::Fixed(2),
+            DataObject::SmKeys | DataObject::ExtendedHeaderList => MaxLength::Unlimited,
+        }
+    }
+
+    /// Returns the maximum length of the value for this card, `None` if it is not limited.
+    pub fn max_length_for(
+        self,
+        caps: &ExtendedCapabilities,
+        status: &PasswordStatus,
+    ) -> Option<usize> {
+        match self.max_length() {
+            MaxLength::Fixed(length) => Some(length),
+            MaxLength::SpecialDo => Some(caps.max_special_do_length as usize),
+            MaxLength::Certificate => Some(caps.max_certificate_length as usize),
+            MaxLength::ResettingCode => Some(status.rc_max_length as usize),
+            MaxLength::Unlimited => None,
+        }
+    }
+
+    /// Returns the PUT DATA APDU for the value.
+    pub fn create_put_apdu(self, data: Vec<u8>, extended_length: bool) -> APDU {
+        if self.uses_odd_ins() {
+            apdus::create_apdu_put_data_odd(data, extended_length)
+        } else {
+            apdus::create_apdu_put_data(self.tag(), data, extended_length)
+        }
+    }
+}
+
+/// Checks the access condition against the verification status of the pin in this session.
+pub fn check_access(
+    access: AccessCondition,
+    status: impl FnOnce(PinReference) -> Result<VerificationStatus, TalktoSCError>,
+) -> Result<(), TalktoSCError> {
+    match access {
+        AccessCondition::Always => Ok(()),
+        AccessCondition::Never => Err(TalktoSCError::AccessError(String::from(
+            "the access is never allowed",
+        ))),
+        AccessCondition::Verify(reference) => match status(reference)? {
+            VerificationStatus::Verified => Ok(()),
+            _ => Err(TalktoSCError::AccessError(format!(
+                "{:?} is not verified",
+                reference
+            ))),
+        },
+    }
+}
+
+/// Checks that the value fits the maximum length of the DO.
+pub fn check_length(
+    object: DataObject,
+    data: &[u8],
+    caps: &ExtendedCapabilities,
+    status: &PasswordStatus,
+) -> Result<(), TalktoSCError> {
+    match object.max_length_for(caps, status) {
+        Some(max_length) if data.len() > max_length => {
+            Err(TalktoSCError::InvalidDataError(format!(
+                "Maximum length of the DO {:04X} is {} bytes",
+                object.tag(),
+                max_length
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes the value of the DO with PUT DATA. The pin needed to write the DO must be verified
+/// before, else this fails with `TalktoSCError::AccessError` without sending the value.
+///
+/// # Example
+///
+/// ```
+/// pins::verify_pin(&card, pins::PinReference::Pw3, admin_pin).unwrap();
+/// dataobjects::put_data(&card, DataObject::Url, b"https://example.com/key.asc".to_vec()).unwrap();
+/// ```
+pub fn put_data(
+    card: &impl CardTransport,
+    object: DataObject,
+    data: Vec<u8>,
+) -> Result<(), TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    let caps = ExtendedCapabilities::from_tlv(&tlv).ok_or(TalktoSCError::MissingTagError(0xC0))?;
+    let status = PasswordStatus::from_tlv(&tlv).ok_or(TalktoSCError::MissingTagError(0xC4))?;
+    check_length(object, &data, &caps, &status)?;
+    check_access(object.write_access(), |reference| {
+        pins::get_verification_status(card, reference)
+    })?;
+    let extended_length = tlvs::use_extended_length(&tlv, data.len());
+    crate::send_and_parse(card, object.create_put_apdu(data, extended_length))?.check()?;
+    Ok(())
+}
+
+/// A typed value of a cardholder DO for [put_value].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataValue {
+    /// URL of the public keys (5F50), ASCII only.
+    Url(String),
+    /// Login data (5E).
+    LoginData(String),
+    /// Name (5B), encoded via [cardholder::encode_name].
+    Name {
+        surname: String,
+        given_names: String,
+    },
+    /// Language preferences (5F2D), encoded via [cardholder::encode_languages].
+    Languages(Vec<String>),
+    /// Salutation (5F35).
+    Salutation(Salutation),
+}
+
+impl DataValue {
+    /// Returns the DO of the value.
+    pub fn data_object(&self) -> DataObject {
+        match self {
+            DataValue::Url(_) => DataObject::Url,
+            DataValue::LoginData(_) => DataObject::LoginData,
+            DataValue::Name { .. } => DataObject::Name,
+            DataValue::Languages(_) => DataObject::Language,
+            DataValue::Salutation(_) => DataObject::Salutation,
+        }
+    }
+
+    /// Encodes the value as stored in the card.
+    pub fn encode(&self) -> Result<Vec<u8>, TalktoSCError> {
+        match self {
+            DataValue::Url(url) => {
+                if !url.is_ascii() {
+                    return Err(TalktoSCError::InvalidDataError(String::from(
+                        "URL must be ASCII",
+                    )));
+                }
+                Ok(url.as_bytes().to_vec())
+            }
+            DataValue::LoginData(login) => Ok(login.as_bytes().to_vec()),
+            DataValue::Name {
+                surname,
+                given_names,
+            } => cardholder::encode_name(surname, given_names),
+            DataValue::Languages(languages) => cardholder::encode_languages(languages),
+            DataValue::Salutation(salutation) => Ok(vec![salutation.to_byte()]),
+        }
+    }
+}
+
+/// Encodes the value and writes it via [put_data], with the same checks.
+///
+/// # Example
+///
+/// ```
+/// pins::verify_pin(&card, pins::PinReference::Pw3, admin_pin).unwrap();
+/// let url = DataValue::Url(String::from("https://example.com/key.asc"));
+/// dataobjects::put_value(&card, &url).unwrap();
+/// ```
+pub fn put_value(card: &impl CardTransport, value: &DataValue) -> Result<(), TalktoSCError> {
+    put_data(card, value.data_object(), value.encode()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tlvs;
+    use std::fs;
+
+    #[test]
+    fn test_put_data_apdus() {
+        let apdu = DataObject::Url.create_put_apdu(vec![0x41, 0x42], false);
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![0x00, 0xDA, 0x5F, 0x50, 0x02, 0x41, 0x42]]
+        );
+        let apdu = DataObject::LoginData.create_put_apdu(vec![0x41], false);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xDA, 0x00, 0x5E, 0x01, 0x41]]);
+        let apdu = DataObject::Fingerprint(KeySlot::Decryption).create_put_apdu(vec![0x01], false);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xDA, 0x00, 0xC8, 0x01, 0x01]]);
+        // Empty data clears the DO, without Lc and Le.
+        let apdu = DataObject::Url.create_put_apdu(vec![], false);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xDA, 0x5F, 0x50]]);
+        let apdu = DataObject::PrivateUse3.create_put_apdu(vec![], true);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xDA, 0x01, 0x03]]);
+        let apdu = DataObject::ExtendedHeaderList.create_put_apdu(vec![0x4D, 0x00], false);
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![0x00, 0xDB, 0x3F, 0xFF, 0x02, 0x4D, 0x00]]
+        );
+    }
+
+    #[test]
+    fn test_access_table() {
+        assert_eq!(DataObject::Url.read_access(), AccessCondition::Always);
+        assert_eq!(
+            DataObject::Url.write_access(),
+            AccessCondition::Verify(PinReference::Pw3)
+        );
+        assert_eq!(
+            DataObject::PrivateUse1.write_access(),
+            AccessCondition::Verify(PinReference::Pw1ForOthers)
+        );
+        assert_eq!(
+            DataObject::PrivateUse4.read_access(),
+            AccessCondition::Verify(PinReference::Pw3)
+        );
+        assert_eq!(
+            DataObject::ResettingCode.read_access(),
+            AccessCondition::Never
+        );
+        assert_eq!(DataObject::AesKey.read_access(), AccessCondition::Never);
+    }
+
+    #[test]
+    fn test_check_access() {
+        let verified = |_| Ok(VerificationStatus::Verified);
+        let not_verified = |_| Ok(VerificationStatus::NotVerified(3));
+        assert!(check_access(DataObject::Url.write_access(), verified).is_ok());
+        assert!(matches!(
+            check_access(DataObject::Url.write_access(), not_verified),
+            Err(TalktoSCError::AccessError(_))
+        ));
+        assert!(check_access(AccessCondition::Always, not_verified).is_ok());
+        assert!(check_access(AccessCondition::Never, verified).is_err());
+    }
+
+    #[test]
+    fn test_check_length() {
+        let data = fs::read("./data/6e_information_for_25519.binary").unwrap();
+        let tlv = &tlvs::read_list(data, true)[0];
+        let caps = ExtendedCapabilities::from_tlv(tlv).unwrap();
+        let status = PasswordStatus::from_tlv(tlv).unwrap();
+        assert!(check_length(DataObject::Name, &[0x41; 39], &caps, &status).is_ok());
+        assert!(check_length(DataObject::Name, &[0x41; 40], &caps, &status).is_err());
+        let max_length = caps.max_special_do_length as usize;
+        assert!(check_length(DataObject::Url, &vec![0x41; max_length], &caps, &status).is_ok());
+        assert!(
+            check_length(DataObject::Url, &vec![0x41; max_length + 1], &caps, &status).is_err()
+        );
+        let algo = DataObject::AlgorithmAttributes(KeySlot::Signature);
+        assert!(check_length(algo, &vec![0x16; max_length], &caps, &status).is_ok());
+        assert!(check_length(algo, &vec![0x16; max_length + 1], &caps, &status).is_err());
+        assert!(check_length(DataObject::SmKeys, &[0x00; 300], &caps, &status).is_ok());
+    }
+
+    #[test]
+    fn test_data_values() {
+        let value = DataValue::Name {
+            surname: String::from("Das"),
+            given_names: String::from("Kushal"),
+        };
+        assert_eq!(value.data_object(), DataObject::Name);
+        assert_eq!(value.encode().unwrap(), b"Das<<Kushal".to_vec());
+        let value = DataValue::Languages(vec![String::from("EN"), String::from("sv")]);
+        assert_eq!(value.data_object().tag(), 0x5F2D);
+        assert_eq!(value.encode().unwrap(), b"ensv".to_vec());
+        assert!(DataValue::Languages(vec![String::from("eng")])
+            .encode()
+            .is_err());
+        let value = DataValue::Salutation(Salutation::Mrs);
+        assert_eq!(value.encode().unwrap(), vec![0x32]);
+        let value = DataValue::Url(String::from("https://example.com/key.asc"));
+        assert_eq!(value.data_object().tag(), 0x5F50);
+        assert_eq!(
+            value.encode().unwrap(),
+            b"https://example.com/key.asc".to_vec()
+        );
+        assert!(DataValue::Url(String::from("https://exämple.com"))
+            .encode()
+            .is_err());
+        let value = DataValue::LoginData(String::from("kushal"));
+        assert_eq!(value.data_object(), DataObject::LoginData);
+        assert_eq!(value.encode().unwrap(), b"kushal".to_vec());
+    }
+}
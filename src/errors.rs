@@ -21,12 +21,42 @@ pub enum TalktoSCError {
     /// When we can not connect to the smartcard.
     #[error("Failed to connect to the card: {0}")]
     SmartCardConnectionError(String),
+    /// When we fail to send an APDU to the card.
+    #[error("Failed to transmit the APDU: {0}")]
+    TransmitError(String),
     /// Wrong sized data returned from the carc
     #[error("Error in the response length: {0}")]
     ResponseError(usize),
-    /// Pin error
-    #[error("Failed to verify the pin")]
-    PinError,
+    /// The card returned an error status word
+    #[error("The card returned the status {0:02X} {1:02X}")]
+    CardStatusError(u8, u8),
+    /// A tag is missing in the data returned from the card
+    #[error("Missing tag {0:X} in the card response")]
+    MissingTagError(u16),
+    /// Pin error, with the remaining tries if the card told us
+    #[error("Failed to verify the pin, remaining tries: {}", .0.map_or(String::from("unknown"), |tries| tries.to_string()))]
+    PinError(Option<u8>),
+    /// Only one try is left for the pin, so the guarded verification refused to send it
+    #[error("Only one try is left for the pin, refusing to send it")]
+    LastPinTryError,
+    /// Invalid data given to the library
+    #[error("Invalid data: {0}")]
+    InvalidDataError(String),
+    /// The card does not support the requested functionality
+    #[error("The card does not support {0}")]
+    NotSupportedError(String),
+    /// The access condition of the DO is not satisfied
+    #[error("Access condition not satisfied: {0}")]
+    AccessError(String),
+    /// Factory reset error
+    #[error("Failed to reset the card: {0}")]
+    ResetError(String),
+    /// The attestation certificates can not be verified
+    #[error("Failed to verify the attestation: {0}")]
+    AttestationError(String),
+    /// Secure messaging failed, the session is closed
+    #[error("Secure messaging error: {0}")]
+    SecureMessagingError(String),
     /// OTP mode change error
     #[error("Failed to change OTP mode")]
     OtpError,
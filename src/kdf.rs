@@ -0,0 +1,346 @@
+//! Module for the KDF-DO (F9) of the card, to hash the pins on the client side.
+//!
+//! When the KDF-DO is set to the iterated and salted S2K of OpenPGP, the card only gets the hash
+//! of the pins, for VERIFY, CHANGE REFERENCE DATA and RESET RETRY COUNTER. The functions in the
+//! [pins](../pins/index.html) module hash the pins automatically. Read section 4.3.2 Key
+//! derived format of the
+//! [OpenPGP-smart-card-application-3.4.1.pdf](https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf).
+
+use rand_core::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::apdus;
+use crate::crypto::HashAlgorithm;
+use crate::errors::TalktoSCError;
+use crate::pins::{self, Password, PinReference};
+use crate::tlvs;
+use crate::CardTransport;
+
+/// The default PW1 of the card, used for the initial pin hash.
+pub const DEFAULT_PW1: &[u8] = b"123456";
+/// The default PW3 of the card, used for the initial pin hash.
+pub const DEFAULT_PW3: &[u8] = b"12345678";
+/// Length of the salts we create.
+pub const SALT_LENGTH: usize = 8;
+
+/// Parameters of the iterated and salted S2K.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IterSaltedS2k {
+    /// SHA256 or SHA512.
+    pub hash: HashAlgorithm,
+    /// Number of bytes to hash (not the coded count of RFC 4880).
+    pub iterations: u32,
+    pub pw1_salt: Vec<u8>,
+    /// The salt of the Resetting Code, the salt of PW1 is used if missing.
+    pub rc_salt: Option<Vec<u8>>,
+    /// The salt of PW3, the salt of PW1 is used if missing.
+    pub pw3_salt: Option<Vec<u8>>,
+    /// Hash of the default PW1, for the cards to set the pin after a reset.
+    pub pw1_initial_hash: Option<Vec<u8>>,
+    /// Hash of the default PW3, for the cards to set the pin after a reset.
+    pub pw3_initial_hash: Option<Vec<u8>>,
+}
+
+/// The KDF-DO (F9).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KdfDo {
+    /// The pins are sent as they are.
+    None,
+    /// The pins are hashed with the iterated and salted S2K.
+    IterSaltedS2k(IterSaltedS2k),
+}
+
+impl KdfDo {
+    /// Creates a new KDF-DO with random salts (from the given generator, say
+    /// [CardRng](../rng/struct.CardRng.html)), and the initial hashes of the default pins.
+    pub fn new_iter_salted_s2k<R: RngCore + CryptoRng>(
+        hash: HashAlgorithm,
+        iterations: u32,
+        rng: &mut R,
+    ) -> Result<Self, TalktoSCError> {
+        let mut salts = [[0u8; SALT_LENGTH]; 3];
+        for salt in salts.iter_mut() {
+            rng.try_fill_bytes(salt)
+                .map_err(|err| TalktoSCError::InvalidDataError(err.to_string()))?;
+        }
+        let pw1_initial_hash = iterated_salted_s2k(hash, &salts[0], DEFAULT_PW1, iterations)?;
+        let pw3_initial_hash = iterated_salted_s2k(hash, &salts[2], DEFAULT_PW3, iterations)?;
+        Ok(KdfDo::IterSaltedS2k(IterSaltedS2k {
+            hash,
+            iterations,
+            pw1_salt: salts[0].to_vec(),
+            rc_salt: Some(salts[1].to_vec()),
+            pw3_salt: Some(salts[2].to_vec()),
+            pw1_initial_hash: Some(pw1_initial_hash),
+            pw3_initial_hash: Some(pw3_initial_hash),
+        }))
+    }
+
+    /// Parses the value of the KDF-DO, with or without the `F9` tag.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TalktoSCError> {
+        let mut data = data.to_vec();
+        if data.first() == Some(&0xF9) {
+            data = read_tlv(data)?.0.get_v().to_vec();
+        }
+        let mut algorithm = None;
+        let mut hash = None;
+        let mut iterations = None;
+        let mut s2k = IterSaltedS2k {
+            hash: HashAlgorithm::Sha256,
+            iterations: 0,
+            pw1_salt: Vec::new(),
+            rc_salt: None,
+            pw3_salt: None,
+            pw1_initial_hash: None,
+            pw3_initial_hash: None,
+        };
+        while !data.is_empty() {
+            let (tlv, rest) = read_tlv(data)?;
+            data = rest;
+            let value = tlv.get_v().to_vec();
+            match tlv.get_t() {
+                0x81 => algorithm = value.first().copied(),
+                0x82 => hash = value.first().copied(),
+                0x83 if value.len() == 4 => {
+                    iterations = Some(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+                }
+                0x84 => s2k.pw1_salt = value,
+                0x85 => s2k.rc_salt = Some(value),
+                0x86 => s2k.pw3_salt = Some(value),
+                0x87 => s2k.pw1_initial_hash = Some(value),
+                0x88 => s2k.pw3_initial_hash = Some(value),
+                _ => (),
+            }
+        }
+        match algorithm {
+            Some(0x00) | None => Ok(KdfDo::None),
+            Some(0x03) => {
+                s2k.hash = hash
+                    .and_then(HashAlgorithm::from_openpgp_id)
+                    .ok_or(TalktoSCError::MissingTagError(0x82))?;
+                s2k.iterations = iterations.ok_or(TalktoSCError::MissingTagError(0x83))?;
+                if s2k.pw1_salt.is_empty() {
+                    return Err(TalktoSCError::MissingTagError(0x84));
+                }
+                Ok(KdfDo::IterSaltedS2k(s2k))
+            }
+            Some(value) => Err(TalktoSCError::InvalidDataError(format!(
+                "Unknown KDF algorithm {:02X}",
+                value
+            ))),
+        }
+    }
+
+    /// Encodes the KDF-DO as the value for PUT DATA.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let s2k = match self {
+            KdfDo::None => return tlvs::encode_tlv(0x81, &[0x00]),
+            KdfDo::IterSaltedS2k(s2k) => s2k,
+        };
+        let mut res = tlvs::encode_tlv(0x81, &[0x03]);
+        res.extend(tlvs::encode_tlv(0x82, &[s2k.hash.openpgp_id()]));
+        res.extend(tlvs::encode_tlv(0x83, &s2k.iterations.to_be_bytes()));
+        res.extend(tlvs::encode_tlv(0x84, &s2k.pw1_salt));
+        let optionals = [
+            (0x85, &s2k.rc_salt),
+            (0x86, &s2k.pw3_salt),
+            (0x87, &s2k.pw1_initial_hash),
+            (0x88, &s2k.pw3_initial_hash),
+        ];
+        for (tag, value) in optionals.iter() {
+            if let Some(value) = value {
+                res.extend(tlvs::encode_tlv(*tag, value));
+            }
+        }
+        res
+    }
+
+    /// Tells if the pins must be hashed before sending them to the card.
+    pub fn is_active(&self) -> bool {
+        matches!(self, KdfDo::IterSaltedS2k(_))
+    }
+
+    /// Returns the value to be sent to the card for the pin, the hash of the pin if the KDF is
+    /// active, or the pin itself.
+    pub fn hash_pin(&self, password: Password, pin: &[u8]) -> Result<Vec<u8>, TalktoSCError> {
+        let s2k = match self {
+            KdfDo::None => return Ok(pin.to_vec()),
+            KdfDo::IterSaltedS2k(s2k) => s2k,
+        };
+        let salt = match password {
+            Password::Pw1 => None,
+            Password::ResettingCode => s2k.rc_salt.as_ref(),
+            Password::Pw3 => s2k.pw3_salt.as_ref(),
+        }
+        .unwrap_or(&s2k.pw1_salt);
+        iterated_salted_s2k(s2k.hash, salt, pin, s2k.iterations)
+    }
+}
+
+/// Reads one TLV, and returns it with the rest of the data.
+fn read_tlv(data: Vec<u8>) -> Result<(tlvs::TLV, Vec<u8>), TalktoSCError> {
+    tlvs::read_single(data, false).map_err(TalktoSCError::InvalidDataError)
+}
+
+/// Hashes the pin with the iterated and salted S2K (RFC 4880 section 3.7.1.3). The salt and the
+/// pin are hashed again and again till `count` bytes, or once if they are longer than that.
+pub fn iterated_salted_s2k(
+    hash: HashAlgorithm,
+    salt: &[u8],
+    pin: &[u8],
+    count: u32,
+) -> Result<Vec<u8>, TalktoSCError> {
+    match hash {
+        HashAlgorithm::Sha256 => Ok(s2k_with::<Sha256>(salt, pin, count)),
+        HashAlgorithm::Sha512 => Ok(s2k_with::<Sha512>(salt, pin, count)),
+        _ => Err(TalktoSCError::InvalidDataError(format!(
+            "KDF does not support {:?}",
+            hash
+        ))),
+    }
+}
+
+fn s2k_with<D: Digest>(salt: &[u8], pin: &[u8], count: u32) -> Vec<u8> {
+    let mut data = salt.to_vec();
+    data.extend_from_slice(pin);
+    let mut left = std::cmp::max(count as usize, data.len());
+    let mut hasher = D::new();
+    while left > 0 {
+        let length = std::cmp::min(left, data.len());
+        hasher.update(&data[..length]);
+        left -= length;
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Fetches the KDF-DO from the card. Cards without the KDF-DO get [KdfDo::None].
+pub fn get_kdf_do(card: &impl CardTransport) -> Result<KdfDo, TalktoSCError> {
+    let resp = crate::send_and_read_all(card, apdus::create_apdu_get_kdf_do())?;
+    match (resp.sw1, resp.sw2) {
+        (0x90, 0x00) => KdfDo::from_bytes(&resp.get_data()),
+        // Referenced data not found, or the whole command is not supported
+        (0x6A, 0x88) | (0x6D, 0x00) | (0x6B, 0x00) => Ok(KdfDo::None),
+        (sw1, sw2) => Err(TalktoSCError::CardStatusError(sw1, sw2)),
+    }
+}
+
+/// Verifies PW3 and writes the KDF-DO to the card.
+///
+/// Do this right after a factory reset, while the pins are still the default pins. The card then
+/// expects the hashed pins in the next VERIFY, so PW3 must be verified again before changing
+/// the pins.
+///
+/// # Example
+///
+/// ```
+/// let mut rng = rng::CardRng::new(&card).unwrap();
+/// let kdf_do = kdf::KdfDo::new_iter_salted_s2k(HashAlgorithm::Sha256, 0x0200_0000, &mut rng).unwrap();
+/// kdf::set_kdf_do(&card, b"12345678".to_vec(), &kdf_do).unwrap();
+/// ```
+pub fn set_kdf_do(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    kdf_do: &KdfDo,
+) -> Result<(), TalktoSCError> {
+    let caps = crate::get_extended_capabilities(card)?;
+    if !caps.kdf {
+        return Err(TalktoSCError::NotSupportedError(String::from("KDF-DO")));
+    }
+    pins::verify_pin(card, PinReference::Pw3, admin_pin)?;
+    crate::send_and_parse(card, apdus::create_apdu_put_kdf_do(kdf_do.to_bytes()))?.check()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn salt() -> Vec<u8> {
+        vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]
+    }
+
+    #[test]
+    fn test_iterated_salted_s2k() {
+        let hash = iterated_salted_s2k(HashAlgorithm::Sha256, &salt(), b"123456", 100000).unwrap();
+        assert_eq!(
+            hash,
+            vec![
+                0x8f, 0xc7, 0x74, 0x36, 0xde, 0xd3, 0x82, 0x53, 0x77, 0xda, 0x92, 0x1a, 0xcc, 0x4f,
+                0xb1, 0xe0, 0xc6, 0x19, 0xe4, 0x26, 0xb9, 0x26, 0xc3, 0x0f, 0x06, 0xfb, 0xb4, 0x28,
+                0xc6, 0xac, 0x97, 0x01
+            ]
+        );
+        // Less than the salt and the pin, then we hash them once.
+        let hash = iterated_salted_s2k(HashAlgorithm::Sha256, &salt(), b"123456", 0).unwrap();
+        assert_eq!(hash[..4], [0x1a, 0xe0, 0xf1, 0x04]);
+        let hash =
+            iterated_salted_s2k(HashAlgorithm::Sha512, &salt(), b"12345678", 0x10000).unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash[..4], [0x7b, 0x68, 0xe3, 0xf9]);
+        assert!(iterated_salted_s2k(HashAlgorithm::Sha1, &salt(), b"123456", 0).is_err());
+    }
+
+    #[test]
+    fn test_kdf_do_round_trip() {
+        let kdf_do = KdfDo::IterSaltedS2k(IterSaltedS2k {
+            hash: HashAlgorithm::Sha256,
+            iterations: 100000,
+            pw1_salt: salt(),
+            rc_salt: None,
+            pw3_salt: Some(vec![0x09; 8]),
+            pw1_initial_hash: Some(vec![0xAA; 32]),
+            pw3_initial_hash: None,
+        });
+        let data = kdf_do.to_bytes();
+        assert_eq!(
+            data[..15],
+            [
+                0x81, 0x01, 0x03, 0x82, 0x01, 0x08, 0x83, 0x04, 0x00, 0x01, 0x86, 0xA0, 0x84, 0x08,
+                0x01
+            ]
+        );
+        assert_eq!(KdfDo::from_bytes(&data).unwrap(), kdf_do);
+        let wrapped = tlvs::encode_tlv(0xF9, &data);
+        assert_eq!(KdfDo::from_bytes(&wrapped).unwrap(), kdf_do);
+
+        assert_eq!(KdfDo::from_bytes(&[0x81, 0x01, 0x00]).unwrap(), KdfDo::None);
+        assert_eq!(KdfDo::None.to_bytes(), vec![0x81, 0x01, 0x00]);
+        assert!(KdfDo::from_bytes(&[0x81, 0x01, 0x03]).is_err());
+    }
+
+    #[test]
+    fn test_hash_pin() {
+        assert_eq!(
+            KdfDo::None.hash_pin(Password::Pw1, b"123456").unwrap(),
+            b"123456".to_vec()
+        );
+        let kdf_do = KdfDo::IterSaltedS2k(IterSaltedS2k {
+            hash: HashAlgorithm::Sha256,
+            iterations: 100000,
+            pw1_salt: salt(),
+            rc_salt: None,
+            pw3_salt: Some(vec![0x09; 8]),
+            pw1_initial_hash: None,
+            pw3_initial_hash: None,
+        });
+        let pw1 = kdf_do.hash_pin(Password::Pw1, b"123456").unwrap();
+        assert_eq!(pw1[..4], [0x8f, 0xc7, 0x74, 0x36]);
+        // Resetting Code falls back to the PW1 salt
+        assert_eq!(
+            kdf_do.hash_pin(Password::ResettingCode, b"123456").unwrap(),
+            pw1
+        );
+        assert_ne!(kdf_do.hash_pin(Password::Pw3, b"123456").unwrap(), pw1);
+    }
+
+    #[test]
+    fn test_kdf_do_apdus() {
+        let apdu = apdus::create_apdu_get_kdf_do();
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xCA, 0x00, 0xF9, 0x00]]);
+        let apdu = apdus::create_apdu_put_kdf_do(vec![0x81, 0x01, 0x00]);
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![0x00, 0xDA, 0x00, 0xF9, 0x03, 0x81, 0x01, 0x00]]
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! Module to generate new keys on the card, to read the public keys back, and to import the
+//! private keys.
+//!
+//! Read section 7.2.14 GENERATE ASYMMETRIC KEY PAIR and 4.4.3.12 Private Key Template of the
+//! [OpenPGP-smart-card-application-3.4.1.pdf](https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf).
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::algorithms::{AlgorithmAttributes, RsaImportFormat};
+use crate::apdus::{self, APDU};
+use crate::errors::TalktoSCError;
+use crate::pins::{self, PinReference};
+use crate::tlvs::{self, ExtendedCapabilities, KeySlot, TLV};
+use crate::CardTransport;
+
+/// Public key returned by the card in the `7F49` template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    Rsa {
+        /// The modulus (81).
+        modulus: Vec<u8>,
+        /// The public exponent (82).
+        exponent: Vec<u8>,
+    },
+    Ecc {
+        /// The public key (86), as an uncompressed point or the native format of the curve for
+        /// Ed25519 and X25519.
+        point: Vec<u8>,
+    },
+}
+
+impl PublicKey {
+    /// Creates the public key from the TLV containing the `7F49` template.
+    pub fn from_tlv(tlv: &TLV) -> Option<Self> {
+        let template = tlv.find_tag(0x7F49)?;
+        if let Some(point) = template.find_tag(0x86) {
+            return Some(PublicKey::Ecc { point: point.v });
+        }
+        let modulus = template.find_tag(0x81)?;
+        let exponent = template.find_tag(0x82)?;
+        Some(PublicKey::Rsa {
+            modulus: modulus.v,
+            exponent: exponent.v,
+        })
+    }
+}
+
+/// Parses the response data of GENERATE ASYMMETRIC KEY PAIR into a `PublicKey`.
+pub fn parse_public_key(data: Vec<u8>) -> Result<PublicKey, TalktoSCError> {
+    tlvs::read_list(data, true)
+        .first()
+        .and_then(PublicKey::from_tlv)
+        .ok_or(TalktoSCError::MissingTagError(0x7F49))
+}
+
+/// Reads the public key of the existing key in the given slot.
+pub fn read_public_key(
+    card: &impl CardTransport,
+    slot: KeySlot,
+) -> Result<PublicKey, TalktoSCError> {
+    let resp = crate::send_and_read_all(card, apdus::create_apdu_read_public_key(slot))?.check()?;
+    parse_public_key(resp.get_data())
+}
+
+/// Verifies PW3 and generates a new key pair in the given slot. Returns the new public key.
+///
+/// The card does not set the fingerprint or the generation time of the new key, use
+/// [create_apdu_put_generation_timestamp](../apdus/fn.create_apdu_put_generation_timestamp.html)
+/// and [create_apdu_put_fingerprint](../apdus/fn.create_apdu_put_fingerprint.html) afterwards.
+pub fn generate_key(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    slot: KeySlot,
+) -> Result<PublicKey, TalktoSCError> {
+    generate_key_with_progress(card, admin_pin, slot, Duration::from_secs(1), |_| {})
+}
+
+/// Same as [generate_key], but calls `progress` with the elapsed time after every `interval`
+/// while the card is still generating the key. RSA 4096 key generation can take more than a
+/// minute on some cards.
+///
+/// # Example
+///
+/// ```
+/// let key = keys::generate_key_with_progress(
+///     &card,
+///     admin_pin,
+///     tlvs::KeySlot::Signature,
+///     Duration::from_secs(5),
+///     |elapsed| println!("Still generating the key, {} seconds", elapsed.as_secs()),
+/// )
+/// .unwrap();
+/// ```
+pub fn generate_key_with_progress<F: FnMut(Duration)>(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    slot: KeySlot,
+    interval: Duration,
+    progress: F,
+) -> Result<PublicKey, TalktoSCError> {
+    pins::verify_pin(card, PinReference::Pw3, admin_pin)?;
+    let data = send_with_progress(
+        card,
+        apdus::create_apdu_generate_key(slot),
+        interval,
+        progress,
+    )?;
+    parse_public_key(data)
+}
+
+/// Sends the APDU from a different thread, and calls `progress` after every `interval` till the
+/// card answers. Returns the data of the full response.
+fn send_with_progress<F: FnMut(Duration)>(
+    card: &impl CardTransport,
+    apdu: APDU,
+    interval: Duration,
+    mut progress: F,
+) -> Result<Vec<u8>, TalktoSCError> {
+    let (sender, receiver) = mpsc::channel();
+    let start = Instant::now();
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            let _ = sender.send(crate::send_and_read_all(card, apdu));
+        });
+        loop {
+            match receiver.recv_timeout(interval) {
+                Ok(resp) => return Ok(resp?.check()?.get_data()),
+                Err(RecvTimeoutError::Timeout) => progress(start.elapsed()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(TalktoSCError::TransmitError(String::from(
+                        "No response from the card",
+                    )))
+                }
+            }
+        }
+    })
+}
+
+/// Components of an RSA private key, as big endian bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RsaPrivateKey {
+    /// Public exponent.
+    pub e: Vec<u8>,
+    /// First prime.
+    pub p: Vec<u8>,
+    /// Second prime.
+    pub q: Vec<u8>,
+    /// 1/q mod p, only required for the CRT import formats.
+    pub pq: Option<Vec<u8>>,
+    /// d mod (p-1), only required for the CRT import formats.
+    pub dp1: Option<Vec<u8>>,
+    /// d mod (q-1), only required for the CRT import formats.
+    pub dq1: Option<Vec<u8>>,
+    /// Modulus, only required for the import formats with modulus.
+    pub n: Option<Vec<u8>>,
+}
+
+/// Private key to import into the card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrivateKey {
+    Rsa(RsaPrivateKey),
+    Ecc {
+        /// The private scalar.
+        scalar: Vec<u8>,
+        /// The public key (point), required if the algorithm attributes ask for it.
+        public: Option<Vec<u8>>,
+    },
+}
+
+/// Left pads the value with zeros to the given size.
+fn pad(value: &[u8], size: usize) -> Result<Vec<u8>, TalktoSCError> {
+    // Big integers can come with a leading zero byte from the ASN.1 encoding.
+    let mut value = value;
+    while value.len() > size && value[0] == 0 {
+        value = &value[1..];
+    }
+    if value.len() > size {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "Key component of {} bytes is bigger than {} bytes",
+            value.len(),
+            size
+        )));
+    }
+    let mut res = vec![0; size - value.len()];
+    res.extend(value.iter());
+    Ok(res)
+}
+
+/// Returns the required optional RSA component or an error.
+fn required(value: &Option<Vec<u8>>, name: &str) -> Result<Vec<u8>, TalktoSCError> {
+    match value {
+        Some(value) => Ok(value.clone()),
+        None => Err(TalktoSCError::InvalidDataError(format!(
+            "RSA component {} is required for the import format",
+            name
+        ))),
+    }
+}
+
+/// Returns the list of (tag, value) of the private key for the algorithm attributes of the slot.
+fn key_components(
+    algo: &AlgorithmAttributes,
+    key: &PrivateKey,
+) -> Result<Vec<(u16, Vec<u8>)>, TalktoSCError> {
+    let mut res = Vec::new();
+    match (algo, key) {
+        (
+            AlgorithmAttributes::Rsa {
+                modulus_bits,
+                exponent_bits,
+                import_format,
+            },
+            PrivateKey::Rsa(key),
+        ) => {
+            let size = *modulus_bits as usize / 8;
+            let half = size / 2;
+            res.push((0x91, pad(&key.e, (*exponent_bits as usize).div_ceil(8))?));
+            res.push((0x92, pad(&key.p, half)?));
+            res.push((0x93, pad(&key.q, half)?));
+            if let RsaImportFormat::Crt | RsaImportFormat::CrtWithModulus = import_format {
+                res.push((0x94, pad(&required(&key.pq, "1/q mod p")?, half)?));
+                res.push((0x95, pad(&required(&key.dp1, "dp1")?, half)?));
+                res.push((0x96, pad(&required(&key.dq1, "dq1")?, half)?));
+            }
+            match import_format {
+                RsaImportFormat::StandardWithModulus | RsaImportFormat::CrtWithModulus => {
+                    res.push((0x97, pad(&required(&key.n, "n")?, size)?));
+                }
+                RsaImportFormat::Unknown(value) => {
+                    return Err(TalktoSCError::InvalidDataError(format!(
+                        "Unknown RSA import format {}",
+                        value
+                    )))
+                }
+                _ => (),
+            }
+        }
+        (
+            AlgorithmAttributes::Ecc {
+                curve,
+                import_with_public_key,
+                ..
+            },
+            PrivateKey::Ecc { scalar, public },
+        ) => {
+            let scalar = match curve.key_size() {
+                Some(size) => pad(scalar, size)?,
+                None => scalar.clone(),
+            };
+            res.push((0x92, scalar));
+            match public {
+                Some(public) => res.push((0x99, public.clone())),
+                None if *import_with_public_key => {
+                    return Err(TalktoSCError::InvalidDataError(String::from(
+                        "Public key is required for the import format",
+                    )))
+                }
+                None => (),
+            }
+        }
+        _ => {
+            return Err(TalktoSCError::InvalidDataError(format!(
+                "Can not import the key for the algorithm {:?}",
+                algo
+            )))
+        }
+    }
+    Ok(res)
+}
+
+/// Creates the extended header list (4D) to import the private key in the given slot.
+///
+/// The template contains the Control Reference Template of the slot, the Cardholder private key
+/// template (7F48) with the tag and length of each component, and the concatenated values of the
+/// components (5F48).
+pub fn create_extended_header_list(
+    slot: KeySlot,
+    algo: &AlgorithmAttributes,
+    key: &PrivateKey,
+) -> Result<Vec<u8>, TalktoSCError> {
+    let components = key_components(algo, key)?;
+    let mut headers = Vec::new();
+    let mut values = Vec::new();
+    for (tag, value) in components {
+        headers.push(tag as u8);
+        headers.extend(tlvs::encode_length(value.len()));
+        values.extend(value);
+    }
+    let mut data = slot.control_reference_template();
+    data.extend(tlvs::encode_tlv(0x7F48, &headers));
+    data.extend(tlvs::encode_tlv(0x5F48, &values));
+    Ok(tlvs::encode_tlv(0x4D, &data))
+}
+
+/// Verifies PW3 and imports the private key in the given slot.
+///
+/// The key components are formatted according to the algorithm attributes (and the import
+/// format) of the slot. Same as the key generation, the card does not set the fingerprint or the
+/// generation time of the key.
+///
+/// The extended header list goes in one extended length APDU if the card supports it and the
+/// data fits the maximum command length (7F66), else via command chaining. Cards without key
+/// import in the Extended capabilities get `TalktoSCError::NotSupportedError` before PW3 is
+/// verified.
+pub fn import_key(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    slot: KeySlot,
+    key: &PrivateKey,
+) -> Result<(), TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    let caps = ExtendedCapabilities::from_tlv(&tlv).ok_or(TalktoSCError::MissingTagError(0xC0))?;
+    if !caps.key_import {
+        return Err(TalktoSCError::NotSupportedError(String::from("key import")));
+    }
+    let algo = AlgorithmAttributes::from_tlv(&tlv, slot)
+        .ok_or_else(|| TalktoSCError::MissingTagError(slot.algo_attributes_tag()))?;
+    let data = create_extended_header_list(slot, &algo, key)?;
+    let extended_length = tlvs::use_extended_length(&tlv, data.len());
+    pins::verify_pin(card, PinReference::Pw3, admin_pin)?;
+    crate::send_and_parse(card, apdus::create_apdu_import_key(data, extended_length))?.check()?;
+    Ok(())
+}
+
+/// Verifies PW3 and imports the AES key (16 or 32 bytes) for [encipher_aes] and [decipher_aes].
+///
+/// [encipher_aes]: ../crypto/fn.encipher_aes.html
+/// [decipher_aes]: ../crypto/fn.decipher_aes.html
+pub fn import_aes_key(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    key: &[u8],
+) -> Result<(), TalktoSCError> {
+    if key.len() != 16 && key.len() != 32 {
+        return Err(TalktoSCError::InvalidDataError(String::from(
+            "AES key must be 16 or 32 bytes",
+        )));
+    }
+    let caps = crate::get_extended_capabilities(card)?;
+    if !caps.aes {
+        return Err(TalktoSCError::NotSupportedError(String::from("AES")));
+    }
+    pins::verify_pin(card, PinReference::Pw3, admin_pin)?;
+    crate::send_and_parse(card, apdus::create_apdu_put_aes_key(key.to_vec()))?.check()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{Curve, EccAlgorithm};
+
+    #[test]
+    fn test_parse_rsa_public_key() {
+        let mut data = vec![0x7F, 0x49, 0x82, 0x01, 0x09, 0x81, 0x82, 0x01, 0x00];
+        data.extend(vec![0xC1; 256]);
+        data.extend(vec![0x82, 0x03, 0x01, 0x00, 0x01]);
+        let key = parse_public_key(data).unwrap();
+        assert_eq!(
+            key,
+            PublicKey::Rsa {
+                modulus: vec![0xC1; 256],
+                exponent: vec![0x01, 0x00, 0x01],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_ecc_public_key() {
+        let mut data = vec![0x7F, 0x49, 0x22, 0x86, 0x20];
+        data.extend(vec![0xEC; 32]);
+        let key = parse_public_key(data).unwrap();
+        assert_eq!(
+            key,
+            PublicKey::Ecc {
+                point: vec![0xEC; 32]
+            }
+        );
+        assert!(parse_public_key(vec![0x7F, 0x49, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_rsa_extended_header_list() {
+        let algo = AlgorithmAttributes::Rsa {
+            modulus_bits: 2048,
+            exponent_bits: 32,
+            import_format: RsaImportFormat::Standard,
+        };
+        let key = PrivateKey::Rsa(RsaPrivateKey {
+            e: vec![0x01, 0x00, 0x01],
+            p: vec![0xAA; 128],
+            // With the leading zero byte from the ASN.1 encoding
+            q: [vec![0x00], vec![0xBB; 128]].concat(),
+            pq: None,
+            dp1: None,
+            dq1: None,
+            n: None,
+        });
+        let data = create_extended_header_list(KeySlot::Decryption, &algo, &key).unwrap();
+        let mut expected = vec![0x4D, 0x82, 0x01, 0x16, 0xB8, 0x00];
+        expected.extend(vec![
+            0x7F, 0x48, 0x08, 0x91, 0x04, 0x92, 0x81, 0x80, 0x93, 0x81, 0x80,
+        ]);
+        expected.extend(vec![0x5F, 0x48, 0x82, 0x01, 0x04, 0x00, 0x01, 0x00, 0x01]);
+        expected.extend(vec![0xAA; 128]);
+        expected.extend(vec![0xBB; 128]);
+        assert_eq!(data, expected);
+
+        let algo = AlgorithmAttributes::Rsa {
+            modulus_bits: 2048,
+            exponent_bits: 32,
+            import_format: RsaImportFormat::CrtWithModulus,
+        };
+        assert!(create_extended_header_list(KeySlot::Decryption, &algo, &key).is_err());
+    }
+
+    #[test]
+    fn test_ecc_extended_header_list() {
+        let algo = AlgorithmAttributes::Ecc {
+            algorithm: EccAlgorithm::EdDsa,
+            curve: Curve::Ed25519,
+            import_with_public_key: true,
+        };
+        let key = PrivateKey::Ecc {
+            scalar: vec![0x11; 31],
+            public: Some(vec![0x22; 32]),
+        };
+        let data = create_extended_header_list(KeySlot::Signature, &algo, &key).unwrap();
+        let mut expected = vec![
+            0x4D, 0x4C, 0xB6, 0x00, 0x7F, 0x48, 0x04, 0x92, 0x20, 0x99, 0x20, 0x5F, 0x48, 0x40,
+            0x00,
+        ];
+        expected.extend(vec![0x11; 31]);
+        expected.extend(vec![0x22; 32]);
+        assert_eq!(data, expected);
+
+        let key = PrivateKey::Ecc {
+            scalar: vec![0x11; 32],
+            public: None,
+        };
+        assert!(create_extended_header_list(KeySlot::Signature, &algo, &key).is_err());
+    }
+}
@@ -4,10 +4,24 @@
 use apdus::APDU;
 use pcsc::*;
 
+pub mod algorithms;
 pub mod apdus;
+pub mod attestation;
+pub mod cardholder;
+pub mod certificates;
+pub mod crypto;
+pub mod dataobjects;
 pub mod errors;
+pub mod kdf;
+pub mod keys;
+pub mod pins;
+pub mod privateuse;
 pub mod tlvs;
 pub mod response;
+pub mod rng;
+pub mod sm;
+pub mod uif;
+pub mod yubikey;
 
 /// Creates a new connection to the card attached to the first reader and returns the connection,
 /// or the related error.
@@ -63,36 +77,220 @@ pub fn disconnect(card: Card) {
     let _ = card.disconnect(Disposition::LeaveCard);
 }
 
-//pub fn sendapdu(card: &Card, apdu: &[u8]) -> Vec<u8> {
+//pub fn sendapdu(card: &impl CardTransport, apdu: &[u8]) -> Vec<u8> {
 //let mut resp_buffer = [0; MAX_BUFFER_SIZE];
 //let resp = card.transmit(apdu, &mut resp_buffer).unwrap();
 //let val = Vec::from(resp);
 //return val;
 //}
 
+/// The connection used to send the APDUs, either the plain [pcsc::Card] or a card with a secure
+/// messaging session ([sm::SmCard]), where every APDU is wrapped. All the functions talking to the
+/// card take any of them.
+pub trait CardTransport: Sync {
+    /// Sends the APDU (if required in chained way) and returns the response as a vector of `u8`,
+    /// or the transmission errors (say a timeout from the reader).
+    fn transmit_apdu(&self, apdu: &apdus::APDU) -> Result<Vec<u8>, errors::TalktoSCError>;
+}
+
+impl CardTransport for Card {
+    fn transmit_apdu(&self, apdu: &apdus::APDU) -> Result<Vec<u8>, errors::TalktoSCError> {
+        let l = apdu.iapdus.len();
+        let mut i = 0;
+        let mut res: Vec<u8> = Vec::new();
+        // Big enough for the extended length responses
+        let mut resp_buffer = vec![0; MAX_BUFFER_SIZE_EXTENDED];
+        for actual_apdu in apdu {
+            let resp = self
+                .transmit(&actual_apdu[..], &mut resp_buffer)
+                .map_err(|err| errors::TalktoSCError::TransmitError(err.to_string()))?;
+            // TODO: Verify the response
+            //println!("Received: {:#?}", resp);
+            i += 1;
+            if i == l {
+                // TODO: verify the final response
+                res = Vec::from(resp);
+            }
+        }
+        Ok(res)
+    }
+}
+
 /// Sends the given APDU (if required in chained way) to the card and returns the response as a
 /// vector of `u8`.
-pub fn sendapdu(card: &Card, apdu: apdus::APDU) -> Vec<u8> {
-    let l = apdu.iapdus.len();
-    let mut i = 0;
-    let mut res: Vec<u8> = Vec::new();
-    for actual_apdu in &apdu {
-        let mut resp_buffer = [0; MAX_BUFFER_SIZE];
-        let resp = card.transmit(&actual_apdu[..], &mut resp_buffer).unwrap();
-        // TODO: Verify the response
-        //println!("Received: {:#?}", resp);
-        i += 1;
-        if i == l {
-            // TODO: verify the final response
-            res = Vec::from(resp);
+pub fn sendapdu(card: &impl CardTransport, apdu: apdus::APDU) -> Vec<u8> {
+    card.transmit_apdu(&apdu).unwrap()
+}
+
+/// Internal function to send the raw bytes of one APDU to the card.
+pub(crate) fn transmit_raw(card: &Card, apdu: &[u8]) -> Result<Vec<u8>, errors::TalktoSCError> {
+    let mut resp_buffer = vec![0; MAX_BUFFER_SIZE_EXTENDED];
+    let resp = card
+        .transmit(apdu, &mut resp_buffer)
+        .map_err(|err| errors::TalktoSCError::TransmitError(err.to_string()))?;
+    Ok(Vec::from(resp))
+}
+
+/// Helper function to send the APDU and returns the a Result<Response, errors::TalktoSCError>.
+pub fn send_and_parse(
+    card: &impl CardTransport,
+    apdus: APDU,
+) -> Result<response::Response, errors::TalktoSCError> {
+    response::Response::new(card.transmit_apdu(&apdus)?)
+}
+
+/// Sends the APDU and keeps reading (via GET RESPONSE) while the card tells us that more data is
+/// available (0x61 LENGTH). Returns the full response.
+pub fn send_and_read_all(
+    card: &impl CardTransport,
+    apdus: APDU,
+) -> Result<response::Response, errors::TalktoSCError> {
+    let mut resp = send_and_parse(card, apdus)?;
+    let mut data = resp.get_data();
+    while let Some(length) = resp.availble_response() {
+        resp = send_and_parse(card, apdus::create_apdu_for_reading(length))?;
+        data.extend(resp.data.iter());
+    }
+    resp.data = data;
+    Ok(resp)
+}
+
+/// Fetches the Application related data (6E) from the card as a `TLV`.
+pub fn get_application_data(card: &impl CardTransport) -> Result<tlvs::TLV, errors::TalktoSCError> {
+    let resp = send_and_read_all(card, apdus::create_apdu_get_application_data())?.check()?;
+    tlvs::read_list(resp.get_data(), true)
+        .into_iter()
+        .next()
+        .ok_or(errors::TalktoSCError::MissingTagError(0x6E))
+}
+
+/// Fetches the algorithm attributes of the given key slot from the card.
+pub fn get_algorithm_attributes(
+    card: &impl CardTransport,
+    slot: tlvs::KeySlot,
+) -> Result<algorithms::AlgorithmAttributes, errors::TalktoSCError> {
+    let tlv = get_application_data(card)?;
+    algorithms::AlgorithmAttributes::from_tlv(&tlv, slot)
+        .ok_or_else(|| errors::TalktoSCError::MissingTagError(slot.algo_attributes_tag()))
+}
+
+/// Fetches the Security support template (7A) from the card and returns the digital signature
+/// counter.
+///
+/// # Example
+///
+/// ```
+/// let card = create_connection().unwrap();
+/// send_and_parse(&card, apdus::create_apdu_select_openpgp()).unwrap();
+/// println!("Signatures made: {}", get_signature_counter(&card).unwrap());
+/// ```
+pub fn get_signature_counter(card: &impl CardTransport) -> Result<u32, errors::TalktoSCError> {
+    let resp = send_and_parse(card, apdus::create_apdu_get_security_template())?.check()?;
+    let tlvs = tlvs::read_list(resp.get_data(), true);
+    let template = tlvs
+        .first()
+        .and_then(tlvs::SecuritySupportTemplate::from_tlv)
+        .ok_or(errors::TalktoSCError::MissingTagError(0x93))?;
+    Ok(template.signature_counter)
+}
+
+/// Fetches the Extended capabilities (C0) from the card.
+pub fn get_extended_capabilities(
+    card: &impl CardTransport,
+) -> Result<tlvs::ExtendedCapabilities, errors::TalktoSCError> {
+    let tlv = get_application_data(card)?;
+    tlvs::ExtendedCapabilities::from_tlv(&tlv).ok_or(errors::TalktoSCError::MissingTagError(0xC0))
+}
+
+/// Fetches the PW status bytes (C4) from the card.
+pub fn get_password_status(
+    card: &impl CardTransport,
+) -> Result<tlvs::PasswordStatus, errors::TalktoSCError> {
+    let tlv = get_application_data(card)?;
+    tlvs::PasswordStatus::from_tlv(&tlv).ok_or(errors::TalktoSCError::MissingTagError(0xC4))
+}
+
+/// Resets the OpenPGP applet to the factory default state, via TERMINATE DF and ACTIVATE FILE.
+/// All the keys and the data on the card are lost.
+///
+/// The admin pin is verified first, a wrong pin returns the error of [pins::verify_pin] and the
+/// card is not touched. Use [factory_reset_blocking_pw3] without the admin pin. At the end we make
+/// sure that the card does not have any key and the pins can be used again.
+///
+/// # Example
+///
+/// ```
+/// let card = create_connection().unwrap();
+/// send_and_parse(&card, apdus::create_apdu_select_openpgp()).unwrap();
+/// factory_reset(&card, admin_pin).unwrap();
+/// ```
+pub fn factory_reset(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+) -> Result<(), errors::TalktoSCError> {
+    pins::verify_pin(card, pins::PinReference::Pw3, admin_pin)?;
+    terminate_and_activate(card)
+}
+
+/// Same as [factory_reset] for a card without the admin pin: first blocks PW3 by sending wrong
+/// pins to the card, as the SPEC allows TERMINATE DF with a blocked PW3, then resets the card.
+pub fn factory_reset_blocking_pw3(card: &impl CardTransport) -> Result<(), errors::TalktoSCError> {
+    block_pw3(card)?;
+    terminate_and_activate(card)
+}
+
+/// Internal function for TERMINATE DF and ACTIVATE FILE, and to check the state of the card after.
+fn terminate_and_activate(card: &impl CardTransport) -> Result<(), errors::TalktoSCError> {
+    send_and_parse(card, apdus::create_apdu_terminate_df())?.check()?;
+    send_and_parse(card, apdus::create_apdu_activate_file())?.check()?;
+    send_and_parse(card, apdus::create_apdu_select_openpgp())?.check()?;
+
+    // Now the card should be back in the default state.
+    let tlv = get_application_data(card)?;
+    let status =
+        tlvs::PasswordStatus::from_tlv(&tlv).ok_or(errors::TalktoSCError::MissingTagError(0xC4))?;
+    if status.pw1_tries == 0 || status.pw3_tries == 0 {
+        return Err(errors::TalktoSCError::ResetError(String::from(
+            "The pins are still blocked",
+        )));
+    }
+    if let Some(fingerprints) = tlv.get_fingerprints() {
+        if fingerprints.iter().any(|value| *value != 0) {
+            return Err(errors::TalktoSCError::ResetError(String::from(
+                "The keys are still present",
+            )));
         }
     }
-    return res;
+    Ok(())
 }
 
-/// Helper function to send the APDU and returns the a Result<Response, errors::TalktoSCError>.
-pub fn send_and_parse(card: &Card, apdus: APDU) -> Result<response::Response, errors::TalktoSCError> {
-    response::Response::new(sendapdu(&card, apdus))
+/// Internal function to block PW3 by sending a wrong pin till the card says it is blocked.
+///
+/// The wrong pin is encoded like a real one (KDF or PIN block format 2), so that the card counts
+/// it as a wrong pin instead of rejecting the format.
+fn block_pw3(card: &impl CardTransport) -> Result<(), errors::TalktoSCError> {
+    let status = get_password_status(card)?;
+    let pin = pins::get_pin_format(card, &status)?.encode(pins::Password::Pw3, b"00000000")?;
+    // A few extra attempts, in case the card counts differently than the status bytes say.
+    for _ in 0..(status.pw3_tries as usize + 3) {
+        let resp = send_and_parse(card, apdus::create_apdu_verify_pw3(pin.clone()))?;
+        if is_pw3_blocked(&resp)? {
+            return Ok(());
+        }
+    }
+    Err(errors::TalktoSCError::ResetError(String::from(
+        "Failed to block PW3",
+    )))
+}
+
+/// Tells if PW3 is blocked (or by chance verified) from the response of VERIFY with a wrong pin.
+/// Any status other than a wrong or blocked pin stops the blocking.
+fn is_pw3_blocked(resp: &response::Response) -> Result<bool, errors::TalktoSCError> {
+    match (resp.sw1, resp.sw2) {
+        (0x69, 0x83) | (0x63, 0xC0) | (0x90, 0x00) => Ok(true),
+        (0x63, sw2) if sw2 & 0xF0 == 0xC0 => Ok(false),
+        (sw1, sw2) => Err(errors::TalktoSCError::CardStatusError(sw1, sw2)),
+    }
 }
 
 pub fn entry(_pin: Vec<u8>) {
@@ -139,4 +337,19 @@ mod tests {
         assert_eq!(comapdu.iapdus[1][0], 0x10);
         assert_eq!(comapdu.iapdus[2][0], 0x00);
     }
+
+    #[test]
+    fn test_is_pw3_blocked() {
+        let blocked = |sw: Vec<u8>| is_pw3_blocked(&response::Response::new(sw).unwrap());
+        assert!(!blocked(vec![0x63, 0xC2]).unwrap());
+        assert!(blocked(vec![0x63, 0xC0]).unwrap());
+        assert!(blocked(vec![0x69, 0x83]).unwrap());
+        assert!(blocked(vec![0x90, 0x00]).unwrap());
+        // A card which does not accept the format of the pin does not count it.
+        assert!(matches!(
+            blocked(vec![0x67, 0x00]),
+            Err(errors::TalktoSCError::CardStatusError(0x67, 0x00))
+        ));
+        assert!(blocked(vec![0x6A, 0x80]).is_err());
+    }
 }
@@ -0,0 +1,528 @@
+//! Module to verify, change, unblock and reset the pins of the card.
+//!
+//! Every function here checks the length of the new pin against the PW status bytes (C4) of the
+//! card before sending it. When the KDF-DO of the card is active, the pins are hashed before
+//! sending, see the [kdf](../kdf/index.html) module. When the PW status bytes ask for the PIN block
+//! format 2, the pins are sent as ISO 9564 format 2 PIN blocks, see [PinFormat].
+
+use crate::apdus::{self, APDU};
+use crate::errors::TalktoSCError;
+use crate::kdf::{self, KdfDo};
+use crate::response::Response;
+use crate::tlvs::{ExtendedCapabilities, PasswordStatus};
+use crate::CardTransport;
+
+/// Minimum length of PW1 as defined in the SPEC.
+pub const PW1_MIN_LENGTH: usize = 6;
+/// Minimum length of the Resetting Code as defined in the SPEC.
+pub const RC_MIN_LENGTH: usize = 8;
+/// Minimum length of PW3 as defined in the SPEC.
+pub const PW3_MIN_LENGTH: usize = 8;
+
+/// The passwords of the OpenPGP applet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Password {
+    /// The user pin.
+    Pw1,
+    /// The Resetting Code.
+    ResettingCode,
+    /// The admin pin.
+    Pw3,
+}
+
+impl Password {
+    /// Returns the minimum length of the password.
+    pub fn min_length(self) -> usize {
+        match self {
+            Password::Pw1 => PW1_MIN_LENGTH,
+            Password::ResettingCode => RC_MIN_LENGTH,
+            Password::Pw3 => PW3_MIN_LENGTH,
+        }
+    }
+
+    /// Returns the maximum length of the password from the PW status bytes.
+    pub fn max_length(self, status: &PasswordStatus) -> usize {
+        match self {
+            Password::Pw1 => status.pw1_max_length as usize,
+            Password::ResettingCode => status.rc_max_length as usize,
+            Password::Pw3 => status.pw3_max_length as usize,
+        }
+    }
+}
+
+/// The references used in VERIFY, PW1 has two different references for signing and for the other
+/// operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinReference {
+    /// PW1 for PSO: COMPUTE DIGITAL SIGNATURE (0x81).
+    Pw1ForSign,
+    /// PW1 for the other operations like decryption and authentication (0x82).
+    Pw1ForOthers,
+    /// PW3 for the admin operations (0x83).
+    Pw3,
+}
+
+impl PinReference {
+    /// Returns the P2 value for VERIFY.
+    pub fn p2(self) -> u8 {
+        match self {
+            PinReference::Pw1ForSign => 0x81,
+            PinReference::Pw1ForOthers => 0x82,
+            PinReference::Pw3 => 0x83,
+        }
+    }
+
+    /// Returns the password behind the reference.
+    pub fn password(self) -> Password {
+        match self {
+            PinReference::Pw1ForSign | PinReference::Pw1ForOthers => Password::Pw1,
+            PinReference::Pw3 => Password::Pw3,
+        }
+    }
+
+    /// Returns the VERIFY APDU for the given pin.
+    pub fn create_verify_apdu(self, pin: Vec<u8>) -> APDU {
+        match self {
+            PinReference::Pw1ForSign => apdus::create_apdu_verify_pw1_for_sign(pin),
+            PinReference::Pw1ForOthers => apdus::create_apdu_verify_pw1_for_others(pin),
+            PinReference::Pw3 => apdus::create_apdu_verify_pw3(pin),
+        }
+    }
+}
+
+/// Verification status of a pin, as returned by VERIFY without any data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationStatus {
+    /// The pin is already verified in this session.
+    Verified,
+    /// The pin is not verified, with the remaining tries.
+    NotVerified(u8),
+    /// The pin is blocked.
+    Blocked,
+}
+
+/// Length of the ISO 9564 format 2 PIN block.
+pub const PIN_BLOCK_LENGTH: usize = 8;
+
+/// Encodes the pin (4 to 12 ASCII digits) as ISO 9564 format 2 PIN block: the control nibble
+/// `2`, the number of digits, the digits and `F` as filler.
+///
+/// # Example
+///
+/// ```
+/// let block = pins::encode_pin_block_2(b"123456").unwrap();
+/// assert_eq!(block, vec![0x26, 0x12, 0x34, 0x56, 0xFF, 0xFF, 0xFF, 0xFF]);
+/// ```
+pub fn encode_pin_block_2(pin: &[u8]) -> Result<Vec<u8>, TalktoSCError> {
+    if pin.len() < 4 || pin.len() > 12 || !pin.iter().all(|x| x.is_ascii_digit()) {
+        return Err(TalktoSCError::InvalidDataError(String::from(
+            "PIN block needs 4 to 12 digits",
+        )));
+    }
+    let mut nibbles = vec![0x02, pin.len() as u8];
+    nibbles.extend(pin.iter().map(|x| x - b'0'));
+    nibbles.resize(PIN_BLOCK_LENGTH * 2, 0x0F);
+    Ok(nibbles
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect())
+}
+
+/// Decodes the ISO 9564 format 2 PIN block, and returns the pin as ASCII digits.
+pub fn decode_pin_block_2(block: &[u8]) -> Result<Vec<u8>, TalktoSCError> {
+    let wrong = || TalktoSCError::InvalidDataError(String::from("Wrong PIN block format 2"));
+    if block.len() != PIN_BLOCK_LENGTH || block[0] >> 4 != 0x02 {
+        return Err(wrong());
+    }
+    let length = (block[0] & 0x0F) as usize;
+    if !(4..=12).contains(&length) {
+        return Err(wrong());
+    }
+    let nibbles: Vec<u8> = block[1..]
+        .iter()
+        .flat_map(|x| vec![x >> 4, x & 0x0F])
+        .collect();
+    let (digits, filler) = nibbles.split_at(length);
+    if digits.iter().any(|x| *x > 9) || filler.iter().any(|x| *x != 0x0F) {
+        return Err(wrong());
+    }
+    Ok(digits.iter().map(|x| x + b'0').collect())
+}
+
+/// How the card expects the pins, from the PW status bytes (C4), the Extended capabilities (C0)
+/// and the KDF-DO (F9).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinFormat {
+    pub kdf: KdfDo,
+    /// PW1 (and the Resetting Code) as PIN block format 2.
+    pub pw1_pin_block: bool,
+    /// PW3 as PIN block format 2.
+    pub pw3_pin_block: bool,
+}
+
+impl PinFormat {
+    /// Creates the format from the KDF-DO and the PW status bytes. The PIN block format 2 is only
+    /// used if the Extended capabilities also announce it.
+    pub fn new(kdf: KdfDo, status: &PasswordStatus, caps: &ExtendedCapabilities) -> Self {
+        PinFormat {
+            kdf,
+            pw1_pin_block: caps.pin_block_2_format && status.pw1_pin_block,
+            pw3_pin_block: caps.pin_block_2_format && status.pw3_pin_block,
+        }
+    }
+
+    /// Returns the value to be sent to the card for the pin: the PIN block, the hash of the pin
+    /// or the pin itself.
+    pub fn encode(&self, password: Password, pin: &[u8]) -> Result<Vec<u8>, TalktoSCError> {
+        let pin_block = match password {
+            Password::Pw1 | Password::ResettingCode => self.pw1_pin_block,
+            Password::Pw3 => self.pw3_pin_block,
+        };
+        if pin_block {
+            encode_pin_block_2(pin)
+        } else {
+            self.kdf.hash_pin(password, pin)
+        }
+    }
+}
+
+/// Fetches the KDF-DO and the Extended capabilities of the card for the format of the pins.
+pub fn get_pin_format(
+    card: &impl CardTransport,
+    status: &PasswordStatus,
+) -> Result<PinFormat, TalktoSCError> {
+    let caps = crate::get_extended_capabilities(card)?;
+    Ok(PinFormat::new(kdf::get_kdf_do(card)?, status, &caps))
+}
+
+/// Checks the length of the pin against the limits of the SPEC and the PW status bytes.
+pub fn check_pin_length(
+    password: Password,
+    pin: &[u8],
+    status: &PasswordStatus,
+) -> Result<(), TalktoSCError> {
+    let min = password.min_length();
+    let max = password.max_length(status);
+    if pin.len() < min || pin.len() > max {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "{:?} must be between {} and {} bytes",
+            password, min, max
+        )));
+    }
+    Ok(())
+}
+
+/// Converts the response of VERIFY, CHANGE REFERENCE DATA or RESET RETRY COUNTER into a result.
+/// Wrong or blocked pins become `TalktoSCError::PinError` with the remaining tries.
+pub fn check_pin_response(resp: Response) -> Result<(), TalktoSCError> {
+    match (resp.sw1, resp.sw2) {
+        (0x90, 0x00) => Ok(()),
+        (0x63, sw2) if sw2 & 0xF0 == 0xC0 => Err(TalktoSCError::PinError(Some(sw2 & 0x0F))),
+        (0x69, 0x83) => Err(TalktoSCError::PinError(Some(0))),
+        (0x63, _) | (0x69, 0x82) => Err(TalktoSCError::PinError(None)),
+        (sw1, sw2) => Err(TalktoSCError::CardStatusError(sw1, sw2)),
+    }
+}
+
+/// Parses the response of VERIFY without any data.
+pub fn parse_verification_status(resp: &Response) -> Result<VerificationStatus, TalktoSCError> {
+    match (resp.sw1, resp.sw2) {
+        (0x90, 0x00) => Ok(VerificationStatus::Verified),
+        (0x63, sw2) if sw2 & 0xF0 == 0xC0 => match sw2 & 0x0F {
+            0 => Ok(VerificationStatus::Blocked),
+            tries => Ok(VerificationStatus::NotVerified(tries)),
+        },
+        (0x69, 0x83) => Ok(VerificationStatus::Blocked),
+        (sw1, sw2) => Err(TalktoSCError::CardStatusError(sw1, sw2)),
+    }
+}
+
+/// Asks the card if the pin is verified, without sending any pin. This does not change the retry
+/// counter.
+pub fn get_verification_status(
+    card: &impl CardTransport,
+    reference: PinReference,
+) -> Result<VerificationStatus, TalktoSCError> {
+    let resp = crate::send_and_parse(card, apdus::create_apdu_get_verification_status(reference))?;
+    parse_verification_status(&resp)
+}
+
+/// Returns the remaining tries of the password from the PW status bytes.
+pub fn get_remaining_tries(
+    card: &impl CardTransport,
+    password: Password,
+) -> Result<u8, TalktoSCError> {
+    let status = crate::get_password_status(card)?;
+    Ok(match password {
+        Password::Pw1 => status.pw1_tries,
+        Password::ResettingCode => status.rc_tries,
+        Password::Pw3 => status.pw3_tries,
+    })
+}
+
+/// Verifies the pin for the given reference. A wrong pin becomes `TalktoSCError::PinError` with
+/// the remaining tries.
+///
+/// # Example
+///
+/// ```
+/// pins::verify_pin(&card, pins::PinReference::Pw3, admin_pin).unwrap();
+/// ```
+pub fn verify_pin(
+    card: &impl CardTransport,
+    reference: PinReference,
+    pin: Vec<u8>,
+) -> Result<(), TalktoSCError> {
+    let status = crate::get_password_status(card)?;
+    let pin = get_pin_format(card, &status)?.encode(reference.password(), &pin)?;
+    check_pin_response(crate::send_and_parse(
+        card,
+        reference.create_verify_apdu(pin),
+    )?)
+}
+
+/// Same as [verify_pin], but refuses to send the pin with `TalktoSCError::LastPinTryError` when
+/// only one try is left, so that a wrong pin can not block the card. Use `force` to send the pin
+/// anyway.
+pub fn verify_pin_guarded(
+    card: &impl CardTransport,
+    reference: PinReference,
+    pin: Vec<u8>,
+    force: bool,
+) -> Result<(), TalktoSCError> {
+    match get_remaining_tries(card, reference.password())? {
+        0 => return Err(TalktoSCError::PinError(Some(0))),
+        1 if !force => return Err(TalktoSCError::LastPinTryError),
+        _ => (),
+    }
+    verify_pin(card, reference, pin)
+}
+
+/// Changes PW1 with the current pin (CHANGE REFERENCE DATA). Both pins are checked against the
+/// lengths in the PW Status Bytes first, so a pin of the wrong length does not use up a try.
+pub fn change_pw1(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    newpin: Vec<u8>,
+) -> Result<(), TalktoSCError> {
+    let status = crate::get_password_status(card)?;
+    check_pin_length(Password::Pw1, &pin, &status)?;
+    check_pin_length(Password::Pw1, &newpin, &status)?;
+    let format = get_pin_format(card, &status)?;
+    let pin = format.encode(Password::Pw1, &pin)?;
+    let newpin = format.encode(Password::Pw1, &newpin)?;
+    let resp = crate::send_and_parse(
+        card,
+        apdus::create_apdu_change_reference_data_pw1(pin, newpin),
+    )?;
+    check_pin_response(resp)
+}
+
+/// Changes PW3 with the current admin pin (CHANGE REFERENCE DATA). Both pins are checked as in
+/// [change_pw1].
+pub fn change_pw3(
+    card: &impl CardTransport,
+    pin: Vec<u8>,
+    newpin: Vec<u8>,
+) -> Result<(), TalktoSCError> {
+    let status = crate::get_password_status(card)?;
+    check_pin_length(Password::Pw3, &pin, &status)?;
+    check_pin_length(Password::Pw3, &newpin, &status)?;
+    let format = get_pin_format(card, &status)?;
+    let pin = format.encode(Password::Pw3, &pin)?;
+    let newpin = format.encode(Password::Pw3, &newpin)?;
+    let resp = crate::send_and_parse(card, apdus::create_apdu_change_pw3(pin, newpin))?;
+    check_pin_response(resp)
+}
+
+/// Unblocks PW1 and sets the new pin with the Resetting Code (RESET RETRY COUNTER, P1 `00`).
+pub fn reset_pw1_with_resetting_code(
+    card: &impl CardTransport,
+    resetting_code: Vec<u8>,
+    newpin: Vec<u8>,
+) -> Result<(), TalktoSCError> {
+    let status = crate::get_password_status(card)?;
+    if status.rc_tries == 0 {
+        return Err(TalktoSCError::PinError(Some(0)));
+    }
+    check_pin_length(Password::ResettingCode, &resetting_code, &status)?;
+    check_pin_length(Password::Pw1, &newpin, &status)?;
+    let format = get_pin_format(card, &status)?;
+    let resetting_code = format.encode(Password::ResettingCode, &resetting_code)?;
+    let newpin = format.encode(Password::Pw1, &newpin)?;
+    let resp = crate::send_and_parse(
+        card,
+        apdus::create_apdu_reset_retry_counter_with_rc(resetting_code, newpin),
+    )?;
+    check_pin_response(resp)
+}
+
+/// Verifies PW3, then unblocks PW1 and sets the new pin (RESET RETRY COUNTER, P1 `02`).
+pub fn reset_pw1_by_admin(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    newpin: Vec<u8>,
+) -> Result<(), TalktoSCError> {
+    let status = crate::get_password_status(card)?;
+    check_pin_length(Password::Pw1, &newpin, &status)?;
+    let newpin = get_pin_format(card, &status)?.encode(Password::Pw1, &newpin)?;
+    verify_pin(card, PinReference::Pw3, admin_pin)?;
+    let resp = crate::send_and_parse(
+        card,
+        apdus::create_apdu_reset_retry_counter_by_admin(newpin),
+    )?;
+    check_pin_response(resp)
+}
+
+/// Verifies PW3, then sets the Resetting Code, or clears it when `resetting_code` is `None`.
+pub fn set_resetting_code(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    resetting_code: Option<Vec<u8>>,
+) -> Result<(), TalktoSCError> {
+    let status = crate::get_password_status(card)?;
+    let mut resetting_code = resetting_code.unwrap_or_default();
+    if !resetting_code.is_empty() {
+        check_pin_length(Password::ResettingCode, &resetting_code, &status)?;
+        resetting_code =
+            get_pin_format(card, &status)?.encode(Password::ResettingCode, &resetting_code)?;
+    }
+    verify_pin(card, PinReference::Pw3, admin_pin)?;
+    crate::send_and_parse(card, apdus::create_apdu_put_resetting_code(resetting_code))?.check()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_pin_length() {
+        let status =
+            PasswordStatus::from_bytes(&[0x00, 0x7F, 0x7F, 0x7F, 0x03, 0x00, 0x03]).unwrap();
+        assert!(check_pin_length(Password::Pw1, b"123456", &status).is_ok());
+        assert!(check_pin_length(Password::Pw1, b"12345", &status).is_err());
+        assert!(check_pin_length(Password::Pw3, b"1234567", &status).is_err());
+        assert!(check_pin_length(Password::ResettingCode, b"12345678", &status).is_ok());
+        assert!(check_pin_length(Password::ResettingCode, b"1234567", &status).is_err());
+
+        let status =
+            PasswordStatus::from_bytes(&[0x00, 0x08, 0x08, 0x08, 0x03, 0x00, 0x03]).unwrap();
+        assert!(check_pin_length(Password::Pw1, b"123456789", &status).is_err());
+        assert!(check_pin_length(Password::ResettingCode, b"123456789", &status).is_err());
+    }
+
+    #[test]
+    fn test_check_pin_response() {
+        let resp = Response::new(vec![0x90, 0x00]).unwrap();
+        assert!(check_pin_response(resp).is_ok());
+        let resp = Response::new(vec![0x63, 0xC2]).unwrap();
+        assert!(matches!(
+            check_pin_response(resp),
+            Err(TalktoSCError::PinError(Some(2)))
+        ));
+        let resp = Response::new(vec![0x69, 0x83]).unwrap();
+        assert!(matches!(
+            check_pin_response(resp),
+            Err(TalktoSCError::PinError(Some(0)))
+        ));
+        let resp = Response::new(vec![0x6A, 0x80]).unwrap();
+        assert!(matches!(
+            check_pin_response(resp),
+            Err(TalktoSCError::CardStatusError(0x6A, 0x80))
+        ));
+    }
+
+    #[test]
+    fn test_verification_status() {
+        let resp = Response::new(vec![0x90, 0x00]).unwrap();
+        assert_eq!(
+            parse_verification_status(&resp).unwrap(),
+            VerificationStatus::Verified
+        );
+        let resp = Response::new(vec![0x63, 0xC3]).unwrap();
+        assert_eq!(
+            parse_verification_status(&resp).unwrap(),
+            VerificationStatus::NotVerified(3)
+        );
+        let resp = Response::new(vec![0x63, 0xC0]).unwrap();
+        assert_eq!(
+            parse_verification_status(&resp).unwrap(),
+            VerificationStatus::Blocked
+        );
+        let resp = Response::new(vec![0x6D, 0x00]).unwrap();
+        assert!(parse_verification_status(&resp).is_err());
+        let apdu = apdus::create_apdu_get_verification_status(PinReference::Pw1ForOthers);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0x20, 0x00, 0x82]]);
+    }
+
+    #[test]
+    fn test_resetting_code_apdus() {
+        let apdu = apdus::create_apdu_put_resetting_code(b"12345678".to_vec());
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![
+                0x00, 0xDA, 0x00, 0xD3, 0x08, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38
+            ]]
+        );
+        // Clearing the Resetting Code is PUT DATA without Lc and Le.
+        let apdu = apdus::create_apdu_put_resetting_code(vec![]);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xDA, 0x00, 0xD3]]);
+    }
+
+    #[test]
+    fn test_pin_block_2() {
+        let block = encode_pin_block_2(b"123456").unwrap();
+        assert_eq!(block, vec![0x26, 0x12, 0x34, 0x56, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(decode_pin_block_2(&block).unwrap(), b"123456".to_vec());
+        let block = encode_pin_block_2(b"123456789012").unwrap();
+        assert_eq!(block, vec![0x2C, 0x12, 0x34, 0x56, 0x78, 0x90, 0x12, 0xFF]);
+        assert_eq!(
+            decode_pin_block_2(&block).unwrap(),
+            b"123456789012".to_vec()
+        );
+
+        assert!(encode_pin_block_2(b"123").is_err());
+        assert!(encode_pin_block_2(b"1234567890123").is_err());
+        assert!(encode_pin_block_2(b"12345a").is_err());
+        assert!(decode_pin_block_2(&[0x16, 0x12, 0x34, 0x56, 0xFF, 0xFF, 0xFF, 0xFF]).is_err());
+        assert!(decode_pin_block_2(&[0x26, 0x12, 0x34, 0x56, 0xFF, 0xFF, 0xFF, 0x00]).is_err());
+        assert!(decode_pin_block_2(&[0x26, 0x12, 0x34, 0x5A, 0xFF, 0xFF, 0xFF, 0xFF]).is_err());
+    }
+
+    #[test]
+    fn test_pin_format() {
+        let status =
+            PasswordStatus::from_bytes(&[0x00, 0x8C, 0x7F, 0x7F, 0x03, 0x00, 0x03]).unwrap();
+        let caps = ExtendedCapabilities::from_bytes(
+            &[0x7D, 0x00, 0x0B, 0xFE, 0x08, 0x00, 0x00, 0xFF, 0x01, 0x00],
+            3,
+        )
+        .unwrap();
+        let format = PinFormat::new(KdfDo::None, &status, &caps);
+        assert_eq!(
+            format.encode(Password::Pw1, b"123456").unwrap(),
+            vec![0x26, 0x12, 0x34, 0x56, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+        assert_eq!(
+            format.encode(Password::ResettingCode, b"12345678").unwrap()[0],
+            0x28
+        );
+        assert_eq!(
+            format.encode(Password::Pw3, b"12345678").unwrap(),
+            b"12345678".to_vec()
+        );
+
+        // The C4 bits alone are not enough.
+        let caps = ExtendedCapabilities {
+            pin_block_2_format: false,
+            ..caps
+        };
+        let format = PinFormat::new(KdfDo::None, &status, &caps);
+        assert!(!format.pw1_pin_block);
+        assert_eq!(
+            format.encode(Password::Pw1, b"123456").unwrap(),
+            b"123456".to_vec()
+        );
+    }
+}
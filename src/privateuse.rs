@@ -0,0 +1,190 @@
+//! Module to read and write the private use DOs (0101 to 0104) of the card.
+//!
+//! The four DOs only differ in the access conditions, see [PrivateUseDo]. The conditions come
+//! from the table in the [dataobjects](../dataobjects/index.html) module. The maximum length of
+//! the value is the maximum length of the special DOs in the Extended capabilities.
+
+use crate::apdus;
+use crate::dataobjects::{AccessCondition, DataObject};
+use crate::errors::TalktoSCError;
+use crate::pins;
+use crate::response::Response;
+use crate::CardTransport;
+
+/// The private use DOs of the OpenPGP applet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivateUseDo {
+    /// Free to read, PW1 (0x82) to write.
+    Do0101,
+    /// Free to read, PW3 to write.
+    Do0102,
+    /// PW1 (0x82) to read and write.
+    Do0103,
+    /// PW3 to read and write.
+    Do0104,
+}
+
+impl PrivateUseDo {
+    /// Returns the matching [DataObject].
+    pub fn data_object(self) -> DataObject {
+        match self {
+            PrivateUseDo::Do0101 => DataObject::PrivateUse1,
+            PrivateUseDo::Do0102 => DataObject::PrivateUse2,
+            PrivateUseDo::Do0103 => DataObject::PrivateUse3,
+            PrivateUseDo::Do0104 => DataObject::PrivateUse4,
+        }
+    }
+
+    /// Returns the tag of the DO.
+    pub fn tag(self) -> u16 {
+        self.data_object().tag()
+    }
+
+    /// Returns the access condition to read the DO.
+    pub fn read_access(self) -> AccessCondition {
+        self.data_object().read_access()
+    }
+
+    /// Returns the access condition to write the DO.
+    pub fn write_access(self) -> AccessCondition {
+        self.data_object().write_access()
+    }
+}
+
+/// Verifies the pin of the access condition, if any.
+fn verify_access(
+    card: &impl CardTransport,
+    pdo: PrivateUseDo,
+    access: AccessCondition,
+    pin: Option<Vec<u8>>,
+) -> Result<(), TalktoSCError> {
+    match access {
+        AccessCondition::Always => Ok(()),
+        AccessCondition::Verify(reference) => {
+            let pin = pin.ok_or_else(|| {
+                TalktoSCError::InvalidDataError(format!(
+                    "A pin is needed to access the private use DO {:04X}",
+                    pdo.tag()
+                ))
+            })?;
+            pins::verify_pin(card, reference, pin)
+        }
+        AccessCondition::Never => Err(TalktoSCError::AccessError(format!(
+            "the private use DO {:04X} can not be accessed",
+            pdo.tag()
+        ))),
+    }
+}
+
+/// Returns the maximum length of the private use DOs, or an error if the card does not have them.
+fn get_max_length(card: &impl CardTransport) -> Result<usize, TalktoSCError> {
+    let caps = crate::get_extended_capabilities(card)?;
+    if !caps.private_use_dos {
+        return Err(TalktoSCError::NotSupportedError(String::from(
+            "the private use DOs",
+        )));
+    }
+    Ok(caps.max_special_do_length as usize)
+}
+
+/// Cards without the DO answer with "Referenced data not found" or "Instruction not supported".
+fn check_response(resp: Response, pdo: PrivateUseDo) -> Result<Response, TalktoSCError> {
+    match (resp.sw1, resp.sw2) {
+        (0x6A, 0x88) | (0x6D, 0x00) => Err(TalktoSCError::NotSupportedError(format!(
+            "the private use DO {:04X}",
+            pdo.tag()
+        ))),
+        _ => resp.check(),
+    }
+}
+
+/// Reads the private use DO. The pin is verified first if the DO needs one to read, and is not
+/// used otherwise.
+///
+/// # Example
+///
+/// ```
+/// let data = privateuse::read_private_use_do(&card, PrivateUseDo::Do0103, Some(pin)).unwrap();
+/// ```
+pub fn read_private_use_do(
+    card: &impl CardTransport,
+    pdo: PrivateUseDo,
+    pin: Option<Vec<u8>>,
+) -> Result<Vec<u8>, TalktoSCError> {
+    get_max_length(card)?;
+    verify_access(card, pdo, pdo.read_access(), pin)?;
+    let resp = crate::send_and_read_all(card, apdus::create_apdu_get_private_use_do(pdo.tag()))?;
+    Ok(check_response(resp, pdo)?.get_data())
+}
+
+/// Verifies the pin needed to write the private use DO (PW1 or PW3, see [PrivateUseDo]), and
+/// writes the data. Empty data clears the DO.
+pub fn write_private_use_do(
+    card: &impl CardTransport,
+    pdo: PrivateUseDo,
+    pin: Vec<u8>,
+    data: Vec<u8>,
+) -> Result<(), TalktoSCError> {
+    let max_length = get_max_length(card)?;
+    if data.len() > max_length {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "Maximum length of the private use DO is {} bytes",
+            max_length
+        )));
+    }
+    verify_access(card, pdo, pdo.write_access(), Some(pin))?;
+    let resp = crate::send_and_parse(card, apdus::create_apdu_put_private_use_do(pdo.tag(), data))?;
+    check_response(resp, pdo)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pins::PinReference;
+
+    #[test]
+    fn test_private_use_do_apdus() {
+        let apdu = apdus::create_apdu_get_private_use_do(PrivateUseDo::Do0103.tag());
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xCA, 0x01, 0x03, 0x00]]);
+        let apdu = apdus::create_apdu_put_private_use_do(PrivateUseDo::Do0104.tag(), vec![0x41]);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xDA, 0x01, 0x04, 0x01, 0x41]]);
+        // Empty data clears the DO, without Lc and Le.
+        let apdu = apdus::create_apdu_put_private_use_do(PrivateUseDo::Do0102.tag(), vec![]);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xDA, 0x01, 0x02]]);
+    }
+
+    #[test]
+    fn test_private_use_do_access() {
+        assert_eq!(PrivateUseDo::Do0101.read_access(), AccessCondition::Always);
+        assert_eq!(PrivateUseDo::Do0102.read_access(), AccessCondition::Always);
+        assert_eq!(
+            PrivateUseDo::Do0103.read_access(),
+            AccessCondition::Verify(PinReference::Pw1ForOthers)
+        );
+        assert_eq!(
+            PrivateUseDo::Do0104.read_access(),
+            AccessCondition::Verify(PinReference::Pw3)
+        );
+        assert_eq!(
+            PrivateUseDo::Do0101.write_access(),
+            AccessCondition::Verify(PinReference::Pw1ForOthers)
+        );
+        assert_eq!(
+            PrivateUseDo::Do0102.write_access(),
+            AccessCondition::Verify(PinReference::Pw3)
+        );
+        assert_eq!(PrivateUseDo::Do0104.tag(), 0x0104);
+    }
+
+    #[test]
+    fn test_missing_private_use_do() {
+        let resp = Response::new(vec![0x6A, 0x88]).unwrap();
+        assert!(matches!(
+            check_response(resp, PrivateUseDo::Do0101),
+            Err(TalktoSCError::NotSupportedError(_))
+        ));
+        let resp = Response::new(vec![0x01, 0x90, 0x00]).unwrap();
+        assert!(check_response(resp, PrivateUseDo::Do0101).is_ok());
+    }
+}
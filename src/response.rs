@@ -16,7 +16,7 @@ pub struct Response {
 impl Response {
     /// Creates a new `Response` structure.
     pub fn new(input: Vec<u8>) -> Result<Self, errors::TalktoSCError> {
-        let length = input.len() as usize;
+        let length = input.len();
         if length < 2 {
             return Err(errors::TalktoSCError::ResponseError(length));
         }
@@ -28,10 +28,16 @@ impl Response {
 
     /// Tells if the response is okay (0x90 0x00) or not.
     pub fn is_okay(&self) -> bool {
-        if self.sw1 == 0x90 && self.sw2 == 0x00 {
-            return true
+        self.sw1 == 0x90 && self.sw2 == 0x00
+    }
+
+    /// Returns the response back if it is okay, otherwise the status words as
+    /// `TalktoSCError::CardStatusError`.
+    pub fn check(self) -> Result<Self, errors::TalktoSCError> {
+        if self.is_okay() {
+            Ok(self)
         } else {
-            return false
+            Err(errors::TalktoSCError::CardStatusError(self.sw1, self.sw2))
         }
     }
 
@@ -44,8 +50,8 @@ impl Response {
     /// 0x61 LENGTH_TO_BE_READ are the values for sw1 and sw2.
     pub fn availble_response(&self) -> Option<u8> {
         match (self.sw1, self.sw2) {
-            (0x61, value) => return Some(value),
-            (_, _) => return None,
+            (0x61, value) => Some(value),
+            (_, _) => None,
         }
     }
 }
@@ -57,23 +63,34 @@ mod tests {
     #[test]
     fn test_two_bytes_data_response() {
         let res  = Response::new(vec![0x01, 0x02, 0x90, 0x00]).unwrap();
-        assert_eq!(res.is_okay(), true);
+        assert!(res.is_okay());
         assert_eq!(res.get_data(), vec![0x01, 0x02]);
     }
     #[test]
     fn test_no_data_response() {
         let res  = Response::new(vec![0x90, 0x00]).unwrap();
-        assert_eq!(res.is_okay(), true);
+        assert!(res.is_okay());
         assert_eq!(res.get_data(), vec![]);
     }
 
     #[test]
     fn test_more_data_response() {
         let res  = Response::new(vec![0xAB, 0x61, 0x02]).unwrap();
-        assert_eq!(res.is_okay(), false);
+        assert!(!res.is_okay());
         assert_eq!(res.get_data(), vec![0xAB]);
         assert_eq!(res.availble_response().unwrap(), 2);
     }
 
+    #[test]
+    fn test_check_response() {
+        let res = Response::new(vec![0x01, 0x90, 0x00]).unwrap();
+        assert_eq!(res.check().unwrap().get_data(), vec![0x01]);
+        let res = Response::new(vec![0x6A, 0x88]).unwrap();
+        assert!(matches!(
+            res.check(),
+            Err(errors::TalktoSCError::CardStatusError(0x6A, 0x88))
+        ));
+    }
+
 
 }
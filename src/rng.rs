@@ -0,0 +1,179 @@
+//! Module to use the card as a random number generator via GET CHALLENGE.
+//!
+//! [CardRng] implements `rand_core::RngCore` and `rand_core::CryptoRng`, so it can be used with
+//! any code expecting a cryptographically secure random number generator.
+
+use rand_core::{CryptoRng, RngCore};
+
+use crate::apdus;
+use crate::errors::TalktoSCError;
+use crate::tlvs::{ExtendedCapabilities, ExtendedLengthInformation};
+use crate::CardTransport;
+
+/// Maximum length of a challenge without extended length, Le `00`.
+pub const SHORT_MAX_CHALLENGE_LENGTH: u16 = 256;
+
+/// Fetches `length` random bytes from the card (GET CHALLENGE).
+///
+/// The length can not be more than the maximum challenge length in the Extended capabilities,
+/// and not more than 256 bytes if the card does not support extended length.
+pub fn get_challenge(card: &impl CardTransport, length: u16) -> Result<Vec<u8>, TalktoSCError> {
+    if length == 0 {
+        return Err(TalktoSCError::InvalidDataError(String::from(
+            "Challenge length must be at least 1 byte",
+        )));
+    }
+    let max_length = get_max_challenge_length(card)?;
+    if length > max_length {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "Maximum challenge length is {} bytes",
+            max_length
+        )));
+    }
+    read_challenge(card, length)
+}
+
+/// Returns the maximum challenge length for one GET CHALLENGE, or an error if the card does not
+/// support GET CHALLENGE.
+fn get_max_challenge_length(card: &impl CardTransport) -> Result<u16, TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    let caps = ExtendedCapabilities::from_tlv(&tlv).ok_or(TalktoSCError::MissingTagError(0xC0))?;
+    match max_challenge_length(&caps, ExtendedLengthInformation::from_tlv(&tlv)) {
+        0 => Err(TalktoSCError::NotSupportedError(String::from(
+            "GET CHALLENGE",
+        ))),
+        length => Ok(length),
+    }
+}
+
+/// The maximum challenge length of the card, capped at 256 bytes without extended length. Zero
+/// if the card does not support GET CHALLENGE.
+pub fn max_challenge_length(
+    caps: &ExtendedCapabilities,
+    extended_length: Option<ExtendedLengthInformation>,
+) -> u16 {
+    if !caps.get_challenge {
+        return 0;
+    }
+    let limit = match extended_length {
+        // Room for the status words in the response
+        Some(info) => info.max_response_length.saturating_sub(2),
+        None => SHORT_MAX_CHALLENGE_LENGTH,
+    };
+    std::cmp::min(caps.max_challenge_length, limit)
+}
+
+/// Internal function to send GET CHALLENGE without checking the capabilities.
+fn read_challenge(card: &impl CardTransport, length: u16) -> Result<Vec<u8>, TalktoSCError> {
+    let resp = crate::send_and_read_all(card, apdus::create_apdu_get_challenge(length))?.check()?;
+    let data = resp.get_data();
+    if data.len() != length as usize {
+        return Err(TalktoSCError::ResponseError(data.len()));
+    }
+    Ok(data)
+}
+
+/// Random number generator backed by the card.
+///
+/// The random bytes are fetched from the card in chunks of the maximum challenge length (at most
+/// 256 bytes without extended length), and refilled when used up.
+///
+/// # Example
+///
+/// ```
+/// use rand_core::RngCore;
+///
+/// let mut rng = rng::CardRng::new(&card).unwrap();
+/// let mut seed = [0u8; 32];
+/// rng.fill_bytes(&mut seed);
+/// ```
+pub struct CardRng<'a, C: CardTransport> {
+    card: &'a C,
+    chunk_size: u16,
+    buffer: Vec<u8>,
+}
+
+impl<'a, C: CardTransport> CardRng<'a, C> {
+    /// Creates a new generator for the card, if the card supports GET CHALLENGE.
+    pub fn new(card: &'a C) -> Result<Self, TalktoSCError> {
+        Ok(CardRng {
+            card,
+            chunk_size: get_max_challenge_length(card)?,
+            buffer: Vec::new(),
+        })
+    }
+}
+
+impl<'a, C: CardTransport> RngCore for CardRng<'a, C> {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    /// Panics if the card fails to give us random bytes, use `try_fill_bytes` to get the error.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).unwrap()
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        let mut filled = 0;
+        while filled < dest.len() {
+            if self.buffer.is_empty() {
+                self.buffer =
+                    read_challenge(self.card, self.chunk_size).map_err(rand_core::Error::new)?;
+            }
+            let count = std::cmp::min(dest.len() - filled, self.buffer.len());
+            // Use the bytes from the end, and never hand out the same bytes twice.
+            let start = self.buffer.len() - count;
+            dest[filled..filled + count].copy_from_slice(&self.buffer[start..]);
+            self.buffer.truncate(start);
+            filled += count;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, C: CardTransport> CryptoRng for CardRng<'a, C> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tlvs;
+    use std::fs;
+
+    #[test]
+    fn test_get_challenge_apdu() {
+        let apdu = apdus::create_apdu_get_challenge(32);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0x84, 0x00, 0x00, 0x20]]);
+        let apdu = apdus::create_apdu_get_challenge(256);
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0x84, 0x00, 0x00, 0x00]]);
+        let apdu = apdus::create_apdu_get_challenge(0x0BFE);
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![0x00, 0x84, 0x00, 0x00, 0x00, 0x0B, 0xFE]]
+        );
+    }
+
+    #[test]
+    fn test_max_challenge_length() {
+        let data = fs::read("./data/6e_information_for_25519.binary").unwrap();
+        let tlv = &tlvs::read_list(data, true)[0];
+        let caps = ExtendedCapabilities::from_tlv(tlv).unwrap();
+        let info = ExtendedLengthInformation::from_tlv(tlv);
+        assert_eq!(max_challenge_length(&caps, info), 0x0BFE - 2);
+        assert_eq!(max_challenge_length(&caps, None), 256);
+        let caps = ExtendedCapabilities {
+            max_challenge_length: 64,
+            ..caps
+        };
+        assert_eq!(max_challenge_length(&caps, info), 64);
+        let caps = ExtendedCapabilities {
+            get_challenge: false,
+            ..caps
+        };
+        assert_eq!(max_challenge_length(&caps, info), 0);
+    }
+}
@@ -1,7 +1,11 @@
 //! This module implements ISO7816 TLV format.
 //!
 
+use std::convert::TryFrom;
 use std::fmt::UpperHex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::errors::TalktoSCError;
 
 /// A TLV contails the tag, and the length and also the value (data). Our implementation also
 /// contains a special `subs` attritbute, which contains a list of composite DOs.
@@ -37,11 +41,7 @@ impl TLV {
     /// Tells us if there is any recursive TLV(s) present in the `self.sub` vector. `True` only for
     /// the composite DO(s).
     pub fn if_recursive(&self) -> bool {
-        if self.subs.len() == 0 {
-            false
-        } else {
-            true
-        }
+        !self.subs.is_empty()
     }
 
     /// Recursively (depth first) search for any given tag.
@@ -51,9 +51,8 @@ impl TLV {
         } else if self.if_recursive() {
             for tlv in &self.subs {
                 let res = tlv.find_tag(tag);
-                match res {
-                    Some(res) => return Some(res),
-                    _ => (),
+                if let Some(res) = res {
+                    return Some(res);
                 }
             }
         }
@@ -94,6 +93,7 @@ impl TLV {
     /// This will be only availble under secure messaging, otherwise the transport layer can change
     /// the value.
     /// Read 4.4.3.8 Key information section of the <https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf>.
+    /// Use [parse_key_information] to decode the value.
     pub fn get_key_information(&self) -> Option<Vec<u8>> {
         let tlv = self.find_tag(0xDE)?;
         Some(tlv.v.clone())
@@ -125,15 +125,82 @@ impl TLV {
     }
 
     /// Returns the number of signatures made by the smartcard
+    ///
+    /// Use [SecuritySupportTemplate] to get the value as `u32`.
     pub fn get_number_of_signatures(&self) -> Option<Vec<u8>> {
         let tlv = self.find_tag(0x93)?;
         Some(tlv.v.clone())
     }
     /// Returns the pin retry details
+    ///
+    /// Use [PasswordStatus] to parse the value.
     pub fn get_pin_tries(&self) -> Option<Vec<u8>> {
         let tlv = self.find_tag(0xC4)?;
         Some(tlv.v.clone())
     }
+
+    /// Returns the Extended capabilities (C0) of the card.
+    /// Use [ExtendedCapabilities] to parse the value.
+    pub fn get_extended_capabilities(&self) -> Option<Vec<u8>> {
+        let tlv = self.find_tag(0xC0)?;
+        Some(tlv.v.clone())
+    }
+
+    /// Returns the Extended length information (7F66), the maximum number of bytes for the command
+    /// and the response APDUs. Only the cards supporting extended length have this DO.
+    pub fn get_extended_length_information(&self) -> Option<Vec<u8>> {
+        let tlv = self.find_tag(0x7F66)?;
+        Some(tlv.v.clone())
+    }
+
+    /// Returns the 60 bytes for the 3 CA fingerprints.
+    /// Use [parse_ca_fingerprints] to split them.
+    pub fn get_ca_fingerprints(&self) -> Option<Vec<u8>> {
+        let tlv = self.find_tag(0xC6)?;
+        Some(tlv.v.clone())
+    }
+
+    /// Returns the 12 bytes for the 3 key generation timestamps.
+    /// Use [parse_generation_timestamps] to convert them into `SystemTime`.
+    pub fn get_generation_timestamps(&self) -> Option<Vec<u8>> {
+        let tlv = self.find_tag(0xCD)?;
+        Some(tlv.v.clone())
+    }
+
+    /// Returns the generation time of the key in the given slot, `None` if it is not set.
+    ///
+    /// The individual DO (CE, CF, D0 or DD) is preferred, otherwise the value is taken from the
+    /// list of generation timestamps (CD).
+    pub fn get_generation_timestamp(&self, slot: KeySlot) -> Option<SystemTime> {
+        let data = match self.find_tag(slot.generation_timestamp_tag()) {
+            Some(tlv) => tlv.v,
+            None => {
+                let index = slot.index() * 4;
+                let data = self.get_generation_timestamps()?;
+                data.get(index..index + 4)?.to_vec()
+            }
+        };
+        parse_timestamp(&data)
+    }
+
+    /// Returns the CA fingerprint for the given slot, `None` if it is not set.
+    ///
+    /// The individual DO (CA, CB, CC or DC) is preferred, otherwise the value is taken from the
+    /// list of CA fingerprints (C6).
+    pub fn get_ca_fingerprint(&self, slot: KeySlot) -> Option<Vec<u8>> {
+        let data = match self.find_tag(slot.ca_fingerprint_tag()) {
+            Some(tlv) => tlv.v,
+            None => {
+                let index = slot.index() * 20;
+                let data = self.get_ca_fingerprints()?;
+                data.get(index..index + 20)?.to_vec()
+            }
+        };
+        if data.iter().all(|value| *value == 0) {
+            return None;
+        }
+        Some(data)
+    }
 }
 
 /// Internal function to pop a u8 value from the front of the vector.
@@ -166,7 +233,7 @@ pub fn hexify(value: Vec<u8>) -> String {
     let mut res = String::new();
     for v in value.iter() {
         let hvalue = hex(v);
-        res.push_str(" ");
+        res.push(' ');
         res.push_str(&hvalue);
     }
     res
@@ -181,14 +248,14 @@ pub fn read_single(orig_data: Vec<u8>, recursive: bool) -> Result<(TLV, Vec<u8>)
     let mut subs: Vec<TLV> = Vec::new();
     let data = orig_data.clone();
     let (t, mut data) = get(data);
-    let mut t: u16 = (t & 0xff) as u16;
+    let mut t: u16 = t as u16;
     let mut t2: u16 = 0;
     let composite: bool = (t & 0x20) == 0x20;
     if (t & 0x1f) == 0x1f {
         let p = get(data);
         t2 = p.0 as u16;
         data = p.1;
-        t2 = t2 & 0xff;
+        t2 &= 0xff;
         if (t2 & 0x1f) == 0x1f {
             return Err(String::from("Only two bytes for tags"));
         }
@@ -205,12 +272,12 @@ pub fn read_single(orig_data: Vec<u8>, recursive: bool) -> Result<(TLV, Vec<u8>)
         let p = get(data);
         l = p.0 as u16;
         data = p.1;
-        l = l & 0xff;
+        l &= 0xff;
     } else if l == 0x82 {
         let p = get(data);
         l = p.0 as u16;
         data = p.1;
-        l = l & 0xff;
+        l &= 0xff;
         let p = get(data);
         let second_l = p.0 as u16;
         data = p.1;
@@ -223,14 +290,14 @@ pub fn read_single(orig_data: Vec<u8>, recursive: bool) -> Result<(TLV, Vec<u8>)
     }
 
     //dbg!(hex(l));
-    let len: usize = l.clone() as usize;
+    let len: usize = l as usize;
     //let v = data.drain(0..len).collect();
     // If it is a composite, we need to pass the full value to the recursive call
     // else, we should consume the value from the data, and pass the rest.
-    let v = if composite == true {
-        data[..len].iter().cloned().collect()
+    let v = if composite {
+        data[..len].to_vec()
     } else {
-        data.drain(0..len).into_iter().collect()
+        data.drain(0..len).collect()
     };
 
     // Look at the DO(s) which are marked as C in the section 4.4.1
@@ -267,7 +334,7 @@ pub fn read_list(orig_data: Vec<u8>, recursive: bool) -> Vec<TLV> {
     let mut result: Vec<TLV> = Vec::new();
 
     let mut data = orig_data.clone();
-    while data.len() > 0 {
+    while !data.is_empty() {
         if data[0] == 0xff || data[0] == 0x00 {
             // Then we skip the filler byte
             let p = get(data);
@@ -281,27 +348,530 @@ pub fn read_list(orig_data: Vec<u8>, recursive: bool) -> Vec<TLV> {
     result
 }
 
+/// Encodes the tag and the value into BER-TLV bytes, the opposite of [read_single].
+///
+/// # Example
+///
+/// ```
+/// let point = tlvs::encode_tlv(0x86, &public_key);
+/// let data = tlvs::encode_tlv(0xA6, &tlvs::encode_tlv(0x7F49, &point));
+/// ```
+pub fn encode_tlv(tag: u16, value: &[u8]) -> Vec<u8> {
+    let mut res = Vec::new();
+    if tag > 0xFF {
+        res.extend(tag.to_be_bytes().iter());
+    } else {
+        res.push(tag as u8);
+    }
+    res.extend(encode_length(value.len()));
+    res.extend(value.iter());
+    res
+}
+
+/// Encodes the length field of a BER-TLV.
+///
+/// # Panics
+///
+/// Panics for lengths above `0xFFFF`, no DO of the card can be that long.
+pub fn encode_length(length: usize) -> Vec<u8> {
+    assert!(length <= 0xFFFF, "TLV length {} is above 0xFFFF", length);
+    if length < 0x80 {
+        vec![length as u8]
+    } else if length <= 0xFF {
+        vec![0x81, length as u8]
+    } else {
+        let bytes = (length as u16).to_be_bytes();
+        vec![0x82, bytes[0], bytes[1]]
+    }
+}
+
 /// Returns 3 fingerprints as 3 elecment Vec<u8>, (signature, decryption, authentication).
 pub fn parse_fingerprints(data: Vec<u8>) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
     let sig_f = &data[0..20];
     let dec_f = &data[20..40];
     let auth_f = &data[40..60];
-    return (
-        sig_f.iter().cloned().collect(),
-        dec_f.iter().cloned().collect(),
-        auth_f.iter().cloned().collect(),
-    );
+    (sig_f.to_vec(), dec_f.to_vec(), auth_f.to_vec())
+}
+
+/// Returns 3 CA fingerprints as 3 element Vec<u8>, (signature, decryption, authentication).
+pub fn parse_ca_fingerprints(data: Vec<u8>) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    parse_fingerprints(data)
+}
+
+/// Returns the 3 key generation timestamps (signature, decryption, authentication), `None` for
+/// the keys without any timestamp or missing in short data.
+pub fn parse_generation_timestamps(
+    data: Vec<u8>,
+) -> (Option<SystemTime>, Option<SystemTime>, Option<SystemTime>) {
+    (
+        data.get(0..4).and_then(parse_timestamp),
+        data.get(4..8).and_then(parse_timestamp),
+        data.get(8..12).and_then(parse_timestamp),
+    )
+}
+
+/// Converts the 4 bytes timestamp (seconds since the epoch) from the card into `SystemTime`.
+/// Returns `None` for zero (not set) or wrong sized input.
+pub fn parse_timestamp(data: &[u8]) -> Option<SystemTime> {
+    if data.len() != 4 {
+        return None;
+    }
+    let seconds = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    if seconds == 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(seconds as u64))
+}
+
+/// Converts the `SystemTime` into the 4 bytes timestamp used in the card.
+///
+/// The card can only store seconds since the epoch as `u32`, any other time is an error.
+pub fn encode_timestamp(timestamp: SystemTime) -> Result<Vec<u8>, TalktoSCError> {
+    let seconds = timestamp
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|duration| u32::try_from(duration.as_secs()).ok())
+        .ok_or_else(|| {
+            TalktoSCError::InvalidDataError(String::from(
+                "The timestamp must be between 1970 and 2106",
+            ))
+        })?;
+    Ok(seconds.to_be_bytes().to_vec())
 }
 
 /// Returns the serial number of the card from the AID response.
 pub fn parse_card_serial(data: Vec<u8>) -> String {
     let mut res = String::new();
-    for i in 10..14 {
-        res.push_str(&format!("{:02X}", data[i]));
+    for value in &data[10..14] {
+        res.push_str(&format!("{:02X}", value));
     }
     res
 }
 
+/// Manufacturer ID of Yubico in the AID.
+pub const YUBICO_MANUFACTURER_ID: u16 = 0x0006;
+
+/// Returns the manufacturer ID of the card from the AID.
+pub fn parse_manufacturer_id(aid: &[u8]) -> Option<u16> {
+    if aid.len() < 10 {
+        return None;
+    }
+    Some(u16::from_be_bytes([aid[8], aid[9]]))
+}
+
+/// The Security support template (7A) of the card.
+///
+/// # Example
+///
+/// ```
+/// let resp = send_and_parse(&card, apdus::create_apdu_get_security_template()).unwrap();
+/// let tlv = &tlvs::read_list(resp.get_data(), true)[0];
+/// let template = tlvs::SecuritySupportTemplate::from_tlv(tlv).unwrap();
+/// println!("Signatures made: {}", template.signature_counter);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecuritySupportTemplate {
+    /// The digital signature counter (93), 3 bytes in the card.
+    pub signature_counter: u32,
+}
+
+impl SecuritySupportTemplate {
+    /// Creates the template from the TLV returned by the card, `None` if the counter is missing.
+    pub fn from_tlv(tlv: &TLV) -> Option<Self> {
+        let data = tlv.get_number_of_signatures()?;
+        Some(SecuritySupportTemplate {
+            signature_counter: parse_signature_counter(data),
+        })
+    }
+}
+
+/// Returns the digital signature counter (93) value as `u32`.
+pub fn parse_signature_counter(data: Vec<u8>) -> u32 {
+    data.iter()
+        .fold(0_u32, |acc, value| (acc << 8) | *value as u32)
+}
+
+/// The Extended capabilities (C0) of the card.
+///
+/// Read 4.4.3.7 Extended Capabilities section of the <https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendedCapabilities {
+    /// Secure messaging is supported.
+    pub secure_messaging: bool,
+    /// GET CHALLENGE is supported.
+    pub get_challenge: bool,
+    /// Private keys can be imported.
+    pub key_import: bool,
+    /// The PW1 status byte can be changed.
+    pub pw_status_changeable: bool,
+    /// The private use DOs (0101-0104) are available.
+    pub private_use_dos: bool,
+    /// The algorithm attributes can be changed.
+    pub algo_attributes_changeable: bool,
+    /// PSO: DECIPHER and ENCIPHER with AES are supported.
+    pub aes: bool,
+    /// The KDF-DO (F9) and the related functionality are available.
+    pub kdf: bool,
+    /// Secure messaging algorithm, 0 for none, 1 for AES-128, 2 for AES-256, 3 for SCP11b.
+    pub sm_algorithm: u8,
+    /// Maximum length of a challenge for GET CHALLENGE.
+    pub max_challenge_length: u16,
+    /// Maximum length of the Cardholder certificates.
+    pub max_certificate_length: u16,
+    /// Maximum length of the special DOs (private use, login data, URL, KDF-DO and similar).
+    pub max_special_do_length: u16,
+    /// PIN block 2 format is supported.
+    pub pin_block_2_format: bool,
+    /// MANAGE SECURITY ENVIRONMENT is supported for the decryption and authentication keys.
+    pub manage_security_environment: bool,
+}
+
+impl ExtendedCapabilities {
+    /// Parses the 10 bytes of the Extended capabilities. The cards before the SPEC 3.0 use the
+    /// last 4 bytes for the maximum command and response lengths, so `major_version` is required.
+    pub fn from_bytes(data: &[u8], major_version: u8) -> Option<Self> {
+        if data.len() < 10 {
+            return None;
+        }
+        let flags = data[0];
+        let v3 = major_version >= 3;
+        Some(ExtendedCapabilities {
+            secure_messaging: flags & 0x80 == 0x80,
+            get_challenge: flags & 0x40 == 0x40,
+            key_import: flags & 0x20 == 0x20,
+            pw_status_changeable: flags & 0x10 == 0x10,
+            private_use_dos: flags & 0x08 == 0x08,
+            algo_attributes_changeable: flags & 0x04 == 0x04,
+            aes: flags & 0x02 == 0x02,
+            kdf: flags & 0x01 == 0x01,
+            sm_algorithm: data[1],
+            max_challenge_length: u16::from_be_bytes([data[2], data[3]]),
+            max_certificate_length: u16::from_be_bytes([data[4], data[5]]),
+            // The SPEC 2.x allows 254 bytes for the private use DOs.
+            max_special_do_length: if v3 {
+                u16::from_be_bytes([data[6], data[7]])
+            } else {
+                254
+            },
+            pin_block_2_format: v3 && data[8] == 0x01,
+            manage_security_environment: v3 && data[9] == 0x01,
+        })
+    }
+
+    /// Creates the capabilities from the Application related data (6E), the version comes from the
+    /// AID.
+    pub fn from_tlv(tlv: &TLV) -> Option<Self> {
+        let aid = tlv.get_aid()?;
+        let major_version = *aid.get(6)?;
+        ExtendedCapabilities::from_bytes(&tlv.get_extended_capabilities()?, major_version)
+    }
+}
+
+/// Returns the 3 bytes of the card capabilities (compact TLV tag `73`) from the historical bytes.
+///
+/// Read 6 Historical Bytes section of the <https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf>.
+pub fn parse_card_capabilities(data: &[u8]) -> Option<[u8; 3]> {
+    // The first byte is the category indicator, then the compact TLVs.
+    let mut index = 1;
+    while index < data.len() {
+        let (tag, length) = (data[index] >> 4, (data[index] & 0x0F) as usize);
+        let value = data.get(index + 1..index + 1 + length)?;
+        if tag == 0x07 && length == 3 {
+            return Some([value[0], value[1], value[2]]);
+        }
+        index += 1 + length;
+    }
+    None
+}
+
+/// The Extended length information (7F66) of the card, only for the cards supporting extended
+/// Lc and Le fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendedLengthInformation {
+    /// Maximum number of bytes in a command APDU.
+    pub max_command_length: u16,
+    /// Maximum number of bytes in a response APDU.
+    pub max_response_length: u16,
+}
+
+impl ExtendedLengthInformation {
+    /// Parses the value of 7F66, two `02 02 XX XX` integers.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        match data {
+            [0x02, 0x02, c1, c2, 0x02, 0x02, r1, r2, ..] => Some(ExtendedLengthInformation {
+                max_command_length: u16::from_be_bytes([*c1, *c2]),
+                max_response_length: u16::from_be_bytes([*r1, *r2]),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Creates the information from the Application related data (6E), `None` if the card does
+    /// not support extended length. The card capabilities in the historical bytes must say so,
+    /// when the card has them, and 7F66 must be there.
+    pub fn from_tlv(tlv: &TLV) -> Option<Self> {
+        if let Some(historical) = tlv.get_historical_bytes() {
+            let capabilities = parse_card_capabilities(&historical)?;
+            if capabilities[2] & 0x40 == 0x00 {
+                return None;
+            }
+        }
+        ExtendedLengthInformation::from_bytes(&tlv.get_extended_length_information()?)
+    }
+
+    /// Tells if a command APDU with `length` bytes of data (and a 7 bytes header with extended
+    /// Lc and Le) fits the card.
+    pub fn fits_command(&self, length: usize) -> bool {
+        length + 7 <= self.max_command_length as usize
+    }
+}
+
+/// Tells if a command APDU with `length` bytes of data can be sent with extended length, from
+/// the capabilities in the Application related data (6E). Else it must be sent via command
+/// chaining.
+pub fn use_extended_length(tlv: &TLV, length: usize) -> bool {
+    ExtendedLengthInformation::from_tlv(tlv)
+        .map(|info| info.fits_command(length))
+        .unwrap_or(false)
+}
+
+/// The PW status bytes (C4) of the card.
+///
+/// Read 4.4.3.3 PW Status Bytes section of the <https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordStatus {
+    /// `false` if PW1 is only valid for one PSO: CDS command.
+    pub pw1_valid_for_multiple_signatures: bool,
+    /// Maximum length of PW1.
+    pub pw1_max_length: u8,
+    /// `true` if PW1 must be sent as a format 2 PIN block.
+    pub pw1_pin_block: bool,
+    /// Maximum length of the Resetting Code.
+    pub rc_max_length: u8,
+    /// Maximum length of PW3.
+    pub pw3_max_length: u8,
+    /// `true` if PW3 must be sent as a format 2 PIN block.
+    pub pw3_pin_block: bool,
+    /// Remaining tries for PW1.
+    pub pw1_tries: u8,
+    /// Remaining tries for the Resetting Code.
+    pub rc_tries: u8,
+    /// Remaining tries for PW3.
+    pub pw3_tries: u8,
+}
+
+impl PasswordStatus {
+    /// Parses the 7 bytes of the PW status bytes, `None` for wrong sized input.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < 7 {
+            return None;
+        }
+        Some(PasswordStatus {
+            pw1_valid_for_multiple_signatures: data[0] == 0x01,
+            pw1_max_length: data[1] & 0x7F,
+            pw1_pin_block: data[1] & 0x80 == 0x80,
+            rc_max_length: data[2],
+            pw3_max_length: data[3] & 0x7F,
+            pw3_pin_block: data[3] & 0x80 == 0x80,
+            pw1_tries: data[4],
+            rc_tries: data[5],
+            pw3_tries: data[6],
+        })
+    }
+
+    /// Creates the status from the Application related data (6E).
+    pub fn from_tlv(tlv: &TLV) -> Option<Self> {
+        PasswordStatus::from_bytes(&tlv.get_pin_tries()?)
+    }
+}
+
+/// The key slots of the OpenPGP applet, identified by their key reference.
+///
+/// `Attestation` is the vendor specific slot (key reference `0x81`) used by the Yubikeys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeySlot {
+    Signature,
+    Decryption,
+    Authentication,
+    Attestation,
+}
+
+impl KeySlot {
+    /// Returns the key reference of the slot as used in the Key Information DO (DE).
+    pub fn key_ref(&self) -> u8 {
+        match self {
+            KeySlot::Signature => 0x01,
+            KeySlot::Decryption => 0x02,
+            KeySlot::Authentication => 0x03,
+            KeySlot::Attestation => 0x81,
+        }
+    }
+
+    /// Returns the tag of the algorithm attributes DO of the slot.
+    pub fn algo_attributes_tag(&self) -> u16 {
+        match self {
+            KeySlot::Signature => 0xC1,
+            KeySlot::Decryption => 0xC2,
+            KeySlot::Authentication => 0xC3,
+            KeySlot::Attestation => 0xDA,
+        }
+    }
+
+    /// Returns the slot for the given algorithm attributes tag.
+    pub fn from_algo_attributes_tag(tag: u16) -> Option<Self> {
+        match tag {
+            0xC1 => Some(KeySlot::Signature),
+            0xC2 => Some(KeySlot::Decryption),
+            0xC3 => Some(KeySlot::Authentication),
+            0xDA => Some(KeySlot::Attestation),
+            _ => None,
+        }
+    }
+
+    /// Returns the Control Reference Template of the slot, used to select the key in GENERATE
+    /// ASYMMETRIC KEY PAIR and in the extended header list.
+    pub fn control_reference_template(&self) -> Vec<u8> {
+        match self {
+            KeySlot::Signature => vec![0xB6, 0x00],
+            KeySlot::Decryption => vec![0xB8, 0x00],
+            KeySlot::Authentication => vec![0xA4, 0x00],
+            KeySlot::Attestation => vec![0xB6, 0x03, 0x84, 0x01, 0x81],
+        }
+    }
+
+    /// Returns the tag of the fingerprint DO of the slot.
+    pub fn fingerprint_tag(&self) -> u16 {
+        match self {
+            KeySlot::Signature => 0xC7,
+            KeySlot::Decryption => 0xC8,
+            KeySlot::Authentication => 0xC9,
+            KeySlot::Attestation => 0xDB,
+        }
+    }
+
+    /// Returns the tag of the CA fingerprint DO of the slot.
+    pub fn ca_fingerprint_tag(&self) -> u16 {
+        match self {
+            KeySlot::Signature => 0xCA,
+            KeySlot::Decryption => 0xCB,
+            KeySlot::Authentication => 0xCC,
+            KeySlot::Attestation => 0xDC,
+        }
+    }
+
+    /// Returns the tag of the generation timestamp DO of the slot.
+    pub fn generation_timestamp_tag(&self) -> u16 {
+        match self {
+            KeySlot::Signature => 0xCE,
+            KeySlot::Decryption => 0xCF,
+            KeySlot::Authentication => 0xD0,
+            KeySlot::Attestation => 0xDD,
+        }
+    }
+
+    /// Returns the tag of the User Interaction Flag DO (touch policy) of the slot.
+    pub fn uif_tag(&self) -> u16 {
+        match self {
+            KeySlot::Signature => 0xD6,
+            KeySlot::Decryption => 0xD7,
+            KeySlot::Authentication => 0xD8,
+            KeySlot::Attestation => 0xD9,
+        }
+    }
+
+    /// Returns the position of the slot in the lists of fingerprints (C5, C6) and generation
+    /// timestamps (CD).
+    pub fn index(&self) -> usize {
+        match self {
+            KeySlot::Signature => 0,
+            KeySlot::Decryption => 1,
+            KeySlot::Authentication => 2,
+            KeySlot::Attestation => 3,
+        }
+    }
+
+    /// Returns the slot for the given key reference, if we know about it.
+    pub fn from_key_ref(key_ref: u8) -> Option<Self> {
+        match key_ref {
+            0x01 => Some(KeySlot::Signature),
+            0x02 => Some(KeySlot::Decryption),
+            0x03 => Some(KeySlot::Authentication),
+            0x81 => Some(KeySlot::Attestation),
+            _ => None,
+        }
+    }
+}
+
+/// Status of a key as reported by the card in the Key Information DO (DE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStatus {
+    /// No key is present in the slot.
+    NotPresent,
+    /// The key was generated on the card.
+    Generated,
+    /// The key was imported into the card.
+    Imported,
+    /// Any value not defined in the SPEC.
+    Unknown(u8),
+}
+
+impl From<u8> for KeyStatus {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => KeyStatus::NotPresent,
+            0x01 => KeyStatus::Generated,
+            0x02 => KeyStatus::Imported,
+            value => KeyStatus::Unknown(value),
+        }
+    }
+}
+
+/// One entry of the Key Information DO (DE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyInformation {
+    /// The key reference, `0x01`, `0x02`, `0x03` or any vendor specific value.
+    pub key_ref: u8,
+    /// Status of the key in the slot.
+    pub status: KeyStatus,
+}
+
+impl KeyInformation {
+    /// Returns the slot for this entry, `None` for unknown vendor specific key references.
+    pub fn slot(&self) -> Option<KeySlot> {
+        KeySlot::from_key_ref(self.key_ref)
+    }
+}
+
+/// Parses the value of the Key Information DO (DE) into a list of `KeyInformation`, one for each
+/// key reference on the card.
+///
+/// Read 4.4.3.8 Key information section of the <https://gnupg.org/ftp/specs/OpenPGP-smart-card-application-3.4.1.pdf>.
+///
+/// # Example
+///
+/// ```
+/// let data = tlv.get_key_information().unwrap();
+/// for info in tlvs::parse_key_information(data) {
+///     println!("{:?} {:?}", info.slot(), info.status);
+/// }
+/// ```
+pub fn parse_key_information(data: Vec<u8>) -> Vec<KeyInformation> {
+    data.chunks_exact(2)
+        .map(|pair| KeyInformation {
+            key_ref: pair[0],
+            status: KeyStatus::from(pair[1]),
+        })
+        .collect()
+}
+
+/// Returns the status of the key in the given slot from the Key Information DO (DE) value.
+pub fn get_key_status(data: Vec<u8>, slot: KeySlot) -> Option<KeyStatus> {
+    parse_key_information(data)
+        .into_iter()
+        .find(|info| info.key_ref == slot.key_ref())
+        .map(|info| info.status)
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
@@ -320,7 +890,7 @@ mod tests {
         let mut f = File::open(filename).expect("no file found");
         let mut buffer: Vec<u8> = Vec::new();
         f.read_to_end(&mut buffer).unwrap();
-        return buffer;
+        buffer
     }
 
     #[test]
@@ -370,9 +940,220 @@ mod tests {
         assert_eq!(hardcoded, parse_fingerprints(tlv));
     }
 
+    #[test]
+    fn test_parse_generation_timestamps() {
+        let big_box = get_my_tlv("./data/capabilities_tlv.binary");
+        let data = big_box.get_generation_timestamps().unwrap();
+        let (sig_t, dec_t, auth_t) = parse_generation_timestamps(data);
+        assert_eq!(sig_t, Some(UNIX_EPOCH + Duration::from_secs(0x59E621FF)));
+        assert_eq!(dec_t, Some(UNIX_EPOCH + Duration::from_secs(0x59E62227)));
+        assert_eq!(auth_t, Some(UNIX_EPOCH + Duration::from_secs(0x59E62254)));
+        assert_eq!(big_box.get_generation_timestamp(KeySlot::Decryption), dec_t);
+        assert_eq!(
+            encode_timestamp(dec_t.unwrap()).unwrap(),
+            vec![0x59, 0xE6, 0x22, 0x27]
+        );
+
+        let big_box = get_my_tlv("./data/6e_information_for_25519.binary");
+        assert!(big_box
+            .get_generation_timestamp(KeySlot::Signature)
+            .is_some());
+        assert_eq!(big_box.get_generation_timestamp(KeySlot::Attestation), None);
+    }
+
+    #[test]
+    fn test_wrong_generation_timestamps() {
+        assert_eq!(parse_generation_timestamps(vec![]), (None, None, None));
+        let (sig_t, dec_t, auth_t) =
+            parse_generation_timestamps(vec![0x59, 0xE6, 0x21, 0xFF, 0x59, 0xE6]);
+        assert_eq!(sig_t, Some(UNIX_EPOCH + Duration::from_secs(0x59E621FF)));
+        assert_eq!((dec_t, auth_t), (None, None));
+
+        assert!(encode_timestamp(UNIX_EPOCH - Duration::from_secs(1)).is_err());
+        let last = UNIX_EPOCH + Duration::from_secs(u32::MAX as u64);
+        assert_eq!(encode_timestamp(last).unwrap(), vec![0xFF; 4]);
+        assert!(encode_timestamp(last + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_parse_ca_fingerprints() {
+        let big_box = get_my_tlv("./data/capabilities_tlv.binary");
+        let data = big_box.get_ca_fingerprints().unwrap();
+        let (sig_f, dec_f, auth_f) = parse_ca_fingerprints(data);
+        assert_eq!(sig_f, vec![0; 20]);
+        assert_eq!(dec_f, vec![0; 20]);
+        assert_eq!(auth_f, vec![0; 20]);
+        assert_eq!(big_box.get_ca_fingerprint(KeySlot::Signature), None);
+
+        let data = vec![
+            0x6E, 0x16, 0xCB, 0x14, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18,
+            19, 20,
+        ];
+        let tlv = &read_list(data, true)[0];
+        assert_eq!(
+            tlv.get_ca_fingerprint(KeySlot::Decryption).unwrap(),
+            (1..=20).collect::<Vec<u8>>()
+        );
+    }
+
+    #[test]
+    fn test_encode_tlv() {
+        assert_eq!(encode_tlv(0x86, &[1, 2]), vec![0x86, 0x02, 1, 2]);
+        assert_eq!(
+            encode_tlv(0x7F49, &[0; 0x80])[..4],
+            [0x7F, 0x49, 0x81, 0x80]
+        );
+        assert_eq!(
+            encode_tlv(0x5F48, &[0; 0x100])[..5],
+            [0x5F, 0x48, 0x82, 0x01, 0x00]
+        );
+        let data = encode_tlv(0xA6, &encode_tlv(0x7F49, &encode_tlv(0x86, &[0xAB; 32])));
+        let tlv = &read_list(data, true)[0];
+        assert_eq!(tlv.find_tag(0x86).unwrap().v, vec![0xAB; 32]);
+    }
+
+    #[test]
+    fn test_encode_length() {
+        assert_eq!(encode_length(0x7F), vec![0x7F]);
+        assert_eq!(encode_length(0x80), vec![0x81, 0x80]);
+        assert_eq!(encode_length(0xFFFF), vec![0x82, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_encode_length_too_long() {
+        encode_length(0x10000);
+    }
+
     #[test]
     fn test_parse_card_serial_number() {
         let data = read_file("./data/aid.binary");
         assert_eq!(parse_card_serial(data), "14490729");
     }
+
+    #[test]
+    fn test_parse_manufacturer_id() {
+        let data = read_file("./data/aid.binary");
+        assert_eq!(parse_manufacturer_id(&data), Some(YUBICO_MANUFACTURER_ID));
+        assert_eq!(parse_manufacturer_id(&data[..9]), None);
+    }
+
+    #[test]
+    fn test_parse_extended_length_information() {
+        let data = read_file("./data/6e_information_for_25519.binary");
+        let tlv = &read_list(data, true)[0];
+        let historical = tlv.get_historical_bytes().unwrap();
+        assert_eq!(parse_card_capabilities(&historical), Some([0x00, 0x00, 0xE0]));
+        let info = ExtendedLengthInformation::from_tlv(tlv).unwrap();
+        assert_eq!(info.max_command_length, 0x0BFE);
+        assert_eq!(info.max_response_length, 0x0BFE);
+        assert!(info.fits_command(0x0BFE - 7));
+        assert!(!info.fits_command(0x0BFE - 6));
+        assert!(use_extended_length(tlv, 1000));
+        assert!(!use_extended_length(tlv, 0x0BFE));
+
+        // Extended Lc and Le not set in the card capabilities
+        assert_eq!(
+            parse_card_capabilities(&[0x00, 0x73, 0x00, 0x00, 0x80, 0x05, 0x90, 0x00]),
+            Some([0x00, 0x00, 0x80])
+        );
+        let tlv = &read_list(
+            vec![
+                0x6E, 0x15, 0x5F, 0x52, 0x08, 0x00, 0x73, 0x00, 0x00, 0x80, 0x05, 0x90, 0x00, 0x7F,
+                0x66, 0x08, 0x02, 0x02, 0x0B, 0xFE, 0x02, 0x02, 0x0B, 0xFE,
+            ],
+            true,
+        )[0];
+        assert!(ExtendedLengthInformation::from_tlv(tlv).is_none());
+        assert!(!use_extended_length(tlv, 10));
+        assert!(ExtendedLengthInformation::from_bytes(&[0x02, 0x02, 0x0B]).is_none());
+        assert_eq!(parse_card_capabilities(&[0x00, 0x31, 0xC5]), None);
+    }
+
+    #[test]
+    fn test_parse_security_support_template() {
+        let data = vec![0x7A, 0x05, 0x93, 0x03, 0x01, 0x02, 0x03];
+        let tlv = &read_list(data, true)[0];
+        let template = SecuritySupportTemplate::from_tlv(tlv).unwrap();
+        assert_eq!(template.signature_counter, 0x010203);
+        assert_eq!(parse_signature_counter(vec![0x00, 0x00, 0x05]), 5);
+        let tlv = &read_list(vec![0x7A, 0x00], true)[0];
+        assert!(SecuritySupportTemplate::from_tlv(tlv).is_none());
+    }
+
+    #[test]
+    fn test_parse_extended_capabilities() {
+        let big_box = get_my_tlv("./data/6e_information_for_25519.binary");
+        let caps = ExtendedCapabilities::from_tlv(&big_box).unwrap();
+        assert!(!caps.secure_messaging);
+        assert!(caps.get_challenge);
+        assert!(caps.key_import);
+        assert!(caps.pw_status_changeable);
+        assert!(caps.private_use_dos);
+        assert!(caps.algo_attributes_changeable);
+        assert!(!caps.aes);
+        assert!(caps.kdf);
+        assert_eq!(caps.max_challenge_length, 0x0BFE);
+        assert_eq!(caps.max_certificate_length, 0x0800);
+        assert_eq!(caps.max_special_do_length, 0x00FF);
+        assert!(!caps.pin_block_2_format);
+        assert!(!caps.manage_security_environment);
+
+        // Card following the SPEC 2.1
+        let big_box = get_my_tlv("./data/capabilities_tlv.binary");
+        let caps = ExtendedCapabilities::from_tlv(&big_box).unwrap();
+        assert!(!caps.get_challenge);
+        assert_eq!(caps.max_certificate_length, 0x04C0);
+        assert_eq!(caps.max_special_do_length, 254);
+        assert!(!caps.manage_security_environment);
+    }
+
+    #[test]
+    fn test_parse_password_status() {
+        let big_box = get_my_tlv("./data/6e_information_for_25519.binary");
+        let status = PasswordStatus::from_tlv(&big_box).unwrap();
+        assert!(!status.pw1_valid_for_multiple_signatures);
+        assert_eq!(status.pw1_max_length, 0x7F);
+        assert!(!status.pw1_pin_block);
+        assert_eq!(status.pw1_tries, 3);
+        assert_eq!(status.rc_tries, 0);
+        assert_eq!(status.pw3_tries, 3);
+        let status =
+            PasswordStatus::from_bytes(&[0x01, 0x88, 0x08, 0x88, 0x01, 0x02, 0x00]).unwrap();
+        assert!(status.pw1_valid_for_multiple_signatures);
+        assert_eq!(status.pw1_max_length, 8);
+        assert!(status.pw1_pin_block);
+        assert!(status.pw3_pin_block);
+        assert_eq!(status.pw3_tries, 0);
+        assert!(PasswordStatus::from_bytes(&[0x01]).is_none());
+    }
+
+    #[test]
+    fn test_parse_key_information() {
+        let big_box = get_my_tlv("./data/6e_information_for_25519.binary");
+        let data = big_box.get_key_information().unwrap();
+        let infos = parse_key_information(data.clone());
+        assert_eq!(infos.len(), 4);
+        assert_eq!(infos[0].slot(), Some(KeySlot::Signature));
+        assert_eq!(infos[3].slot(), Some(KeySlot::Attestation));
+        for info in infos {
+            assert_eq!(info.status, KeyStatus::Imported);
+        }
+        assert_eq!(
+            get_key_status(data, KeySlot::Decryption),
+            Some(KeyStatus::Imported)
+        );
+    }
+
+    #[test]
+    fn test_parse_key_information_status() {
+        let data = vec![0x01, 0x01, 0x02, 0x00, 0x03, 0x02, 0x82, 0x07];
+        let infos = parse_key_information(data.clone());
+        assert_eq!(infos[0].status, KeyStatus::Generated);
+        assert_eq!(infos[1].status, KeyStatus::NotPresent);
+        assert_eq!(infos[2].status, KeyStatus::Imported);
+        assert_eq!(infos[3].slot(), None);
+        assert_eq!(infos[3].status, KeyStatus::Unknown(0x07));
+        assert_eq!(get_key_status(data, KeySlot::Attestation), None);
+    }
 }
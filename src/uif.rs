@@ -0,0 +1,191 @@
+//! Module for the User Interaction Flags (D6, D7, D8 and D9), the touch policy of each key slot.
+//!
+//! When the touch policy is on, the card waits for the button to be pressed before using the
+//! key. The `*_with_touch` functions in the [crypto](../crypto/index.html) module call back
+//! before sending such operations, so that the user can be asked to touch the card.
+
+use crate::apdus;
+use crate::errors::TalktoSCError;
+use crate::pins::{self, PinReference};
+use crate::tlvs::{KeySlot, TLV};
+use crate::CardTransport;
+
+/// The general feature byte value for a button, the second byte of the UIF.
+pub const BUTTON_FEATURE: u8 = 0x20;
+
+/// The touch policy of a key slot, the first byte of the UIF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPolicy {
+    /// No touch needed.
+    Off,
+    /// Touch needed for every operation.
+    On,
+    /// Same as [TouchPolicy::On], but can not be changed without a factory reset.
+    Permanent,
+    /// Touch needed, but valid for 15 seconds for the next operations.
+    Cached,
+    /// Same as [TouchPolicy::Cached], but can not be changed without a factory reset.
+    PermanentCached,
+    /// Any value not defined by the SPEC or the YubiKey.
+    Unknown(u8),
+}
+
+impl From<u8> for TouchPolicy {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => TouchPolicy::Off,
+            0x01 => TouchPolicy::On,
+            0x02 => TouchPolicy::Permanent,
+            0x03 => TouchPolicy::Cached,
+            0x04 => TouchPolicy::PermanentCached,
+            value => TouchPolicy::Unknown(value),
+        }
+    }
+}
+
+impl TouchPolicy {
+    /// Returns the byte value of the policy.
+    pub fn to_byte(self) -> u8 {
+        match self {
+            TouchPolicy::Off => 0x00,
+            TouchPolicy::On => 0x01,
+            TouchPolicy::Permanent => 0x02,
+            TouchPolicy::Cached => 0x03,
+            TouchPolicy::PermanentCached => 0x04,
+            TouchPolicy::Unknown(value) => value,
+        }
+    }
+
+    /// Tells if the policy can not be changed anymore.
+    pub fn is_permanent(self) -> bool {
+        matches!(self, TouchPolicy::Permanent | TouchPolicy::PermanentCached)
+    }
+
+    /// Tells if the card may wait for a touch before using the key.
+    pub fn requires_touch(self) -> bool {
+        self != TouchPolicy::Off
+    }
+}
+
+/// The User Interaction Flag of a key slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserInteractionFlag {
+    pub policy: TouchPolicy,
+    /// The general feature of the card for the user interaction, [BUTTON_FEATURE] for a button.
+    pub feature: u8,
+}
+
+impl UserInteractionFlag {
+    /// Parses the two bytes of the UIF.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < 2 {
+            return None;
+        }
+        Some(UserInteractionFlag {
+            policy: TouchPolicy::from(data[0]),
+            feature: data[1],
+        })
+    }
+
+    /// Returns the UIF of the given slot from the Application related data (6E), `None` if the
+    /// card does not have it.
+    pub fn from_tlv(tlv: &TLV, slot: KeySlot) -> Option<Self> {
+        let t = tlv.find_tag(slot.uif_tag())?;
+        UserInteractionFlag::from_bytes(t.get_v())
+    }
+
+    /// Encodes the UIF for PUT DATA.
+    pub fn to_bytes(self) -> Vec<u8> {
+        vec![self.policy.to_byte(), self.feature]
+    }
+}
+
+/// Tells if the key of the slot may need a touch, based on the Application related data (6E).
+pub fn requires_touch(tlv: &TLV, slot: KeySlot) -> bool {
+    UserInteractionFlag::from_tlv(tlv, slot)
+        .map(|uif| uif.policy.requires_touch())
+        .unwrap_or(false)
+}
+
+/// Reads the UIF of the key slot from the card.
+pub fn get_uif(
+    card: &impl CardTransport,
+    slot: KeySlot,
+) -> Result<UserInteractionFlag, TalktoSCError> {
+    let tlv = crate::get_application_data(card)?;
+    UserInteractionFlag::from_tlv(&tlv, slot).ok_or_else(|| {
+        TalktoSCError::NotSupportedError(format!("the touch policy of the {:?} key", slot))
+    })
+}
+
+/// Verifies PW3 and sets the touch policy of the key slot. A permanent policy can not be changed.
+///
+/// # Example
+///
+/// ```
+/// uif::set_touch_policy(&card, admin_pin, tlvs::KeySlot::Signature, uif::TouchPolicy::On).unwrap();
+/// ```
+pub fn set_touch_policy(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    slot: KeySlot,
+    policy: TouchPolicy,
+) -> Result<(), TalktoSCError> {
+    let current = get_uif(card, slot)?;
+    if current.policy.is_permanent() && current.policy != policy {
+        return Err(TalktoSCError::InvalidDataError(format!(
+            "The touch policy of the {:?} key is permanent",
+            slot
+        )));
+    }
+    let uif = UserInteractionFlag {
+        policy,
+        feature: current.feature,
+    };
+    pins::verify_pin(card, PinReference::Pw3, admin_pin)?;
+    crate::send_and_parse(card, apdus::create_apdu_put_uif(slot, uif.to_bytes()))?.check()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tlvs;
+    use std::fs;
+
+    #[test]
+    fn test_parse_uif() {
+        let data = fs::read("./data/6e_information_for_25519.binary").unwrap();
+        let tlv = &tlvs::read_list(data, true)[0];
+        for slot in [
+            KeySlot::Signature,
+            KeySlot::Decryption,
+            KeySlot::Authentication,
+            KeySlot::Attestation,
+        ]
+        .iter()
+        {
+            let uif = UserInteractionFlag::from_tlv(tlv, *slot).unwrap();
+            assert_eq!(uif.policy, TouchPolicy::Off);
+            assert_eq!(uif.feature, BUTTON_FEATURE);
+            assert!(!requires_touch(tlv, *slot));
+        }
+    }
+
+    #[test]
+    fn test_uif_bytes() {
+        let uif = UserInteractionFlag::from_bytes(&[0x04, 0x20]).unwrap();
+        assert_eq!(uif.policy, TouchPolicy::PermanentCached);
+        assert!(uif.policy.is_permanent());
+        assert!(uif.policy.requires_touch());
+        assert_eq!(uif.to_bytes(), vec![0x04, 0x20]);
+        assert_eq!(TouchPolicy::from(0x07), TouchPolicy::Unknown(0x07));
+        assert!(UserInteractionFlag::from_bytes(&[0x01]).is_none());
+
+        let apdu = apdus::create_apdu_put_uif(KeySlot::Decryption, vec![0x01, 0x20]);
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![0x00, 0xDA, 0x00, 0xD7, 0x02, 0x01, 0x20]]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Module for the vendor commands of the YubiKey inside the OpenPGP applet.
+//!
+//! The YubiKey has GET VERSION (F1) for the firmware version and SET PIN RETRIES (F2) to change
+//! the retry counters of PW1, the Resetting Code and PW3 together. Other cards do not know these
+//! instructions, so every function here first checks the manufacturer ID in the AID.
+
+use crate::apdus;
+use crate::errors::TalktoSCError;
+use crate::pins::{self, PinReference};
+use crate::tlvs;
+use crate::CardTransport;
+
+/// Firmware version of the YubiKey.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl Version {
+    /// Parses the response of GET VERSION, three BCD encoded bytes.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() != 3 {
+            return None;
+        }
+        Some(Version {
+            major: from_bcd(data[0])?,
+            minor: from_bcd(data[1])?,
+            patch: from_bcd(data[2])?,
+        })
+    }
+}
+
+fn from_bcd(value: u8) -> Option<u8> {
+    let (high, low) = (value >> 4, value & 0x0F);
+    if high > 9 || low > 9 {
+        return None;
+    }
+    Some(high * 10 + low)
+}
+
+/// Tells if the card is a YubiKey, from the manufacturer ID in the AID.
+pub fn is_yubikey(card: &impl CardTransport) -> Result<bool, TalktoSCError> {
+    let aid = crate::get_application_data(card)?
+        .get_aid()
+        .ok_or(TalktoSCError::MissingTagError(0x4F))?;
+    Ok(tlvs::parse_manufacturer_id(&aid) == Some(tlvs::YUBICO_MANUFACTURER_ID))
+}
+
+/// Returns `TalktoSCError::NotSupportedError` for the feature if the card is not a YubiKey.
+pub(crate) fn check_yubikey(card: &impl CardTransport, feature: &str) -> Result<(), TalktoSCError> {
+    if !is_yubikey(card)? {
+        return Err(TalktoSCError::NotSupportedError(String::from(feature)));
+    }
+    Ok(())
+}
+
+/// Returns the firmware version of the YubiKey (GET VERSION).
+///
+/// # Example
+///
+/// ```
+/// let version = yubikey::get_version(&card).unwrap();
+/// println!("{}.{}.{}", version.major, version.minor, version.patch);
+/// ```
+pub fn get_version(card: &impl CardTransport) -> Result<Version, TalktoSCError> {
+    check_yubikey(card, "GET VERSION")?;
+    let resp = crate::send_and_parse(card, apdus::create_apdu_yubikey_get_version())?.check()?;
+    Version::from_bytes(&resp.get_data()).ok_or_else(|| {
+        TalktoSCError::InvalidDataError(String::from("Wrong version from the YubiKey"))
+    })
+}
+
+/// Checks the retry counters for SET PIN RETRIES, all of them must be at least 1.
+fn check_pin_retries(pw1: u8, rc: u8, pw3: u8) -> Result<(), TalktoSCError> {
+    if pw1 == 0 || rc == 0 || pw3 == 0 {
+        return Err(TalktoSCError::InvalidDataError(String::from(
+            "The retry counters must be at least 1",
+        )));
+    }
+    Ok(())
+}
+
+/// Verifies PW3 and sets the retry counters of PW1, the Resetting Code and PW3 (SET PIN RETRIES).
+///
+/// The firmware before 4.0 (the YubiKey NEO) also resets all the pins to their default values,
+/// so change the pins after this on those models.
+///
+/// # Example
+///
+/// ```
+/// yubikey::set_pin_retries(&card, admin_pin, 5, 5, 5).unwrap();
+/// ```
+pub fn set_pin_retries(
+    card: &impl CardTransport,
+    admin_pin: Vec<u8>,
+    pw1: u8,
+    rc: u8,
+    pw3: u8,
+) -> Result<(), TalktoSCError> {
+    check_pin_retries(pw1, rc, pw3)?;
+    check_yubikey(card, "SET PIN RETRIES")?;
+    pins::verify_pin(card, PinReference::Pw3, admin_pin)?;
+    crate::send_and_parse(
+        card,
+        apdus::create_apdu_yubikey_set_pin_retries(pw1, rc, pw3),
+    )?
+    .check()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        let version = Version::from_bytes(&[0x05, 0x04, 0x03]).unwrap();
+        assert_eq!(
+            version,
+            Version {
+                major: 5,
+                minor: 4,
+                patch: 3
+            }
+        );
+        assert_eq!(Version::from_bytes(&[0x05, 0x12, 0x00]).unwrap().minor, 12);
+        assert!(version > Version::from_bytes(&[0x04, 0x03, 0x07]).unwrap());
+        assert!(Version::from_bytes(&[0x05, 0x0A, 0x00]).is_none());
+        assert!(Version::from_bytes(&[0x05, 0x04]).is_none());
+    }
+
+    #[test]
+    fn test_yubikey_apdus() {
+        let apdu = apdus::create_apdu_yubikey_get_version();
+        assert_eq!(apdu.iapdus, vec![vec![0x00, 0xF1, 0x00, 0x00, 0x00]]);
+        let apdu = apdus::create_apdu_yubikey_set_pin_retries(5, 5, 5);
+        assert_eq!(
+            apdu.iapdus,
+            vec![vec![0x00, 0xF2, 0x00, 0x00, 0x03, 0x05, 0x05, 0x05]]
+        );
+    }
+
+    #[test]
+    fn test_check_pin_retries() {
+        assert!(check_pin_retries(3, 3, 3).is_ok());
+        assert!(check_pin_retries(1, 5, 10).is_ok());
+        assert!(check_pin_retries(0, 3, 3).is_err());
+        assert!(check_pin_retries(3, 0, 3).is_err());
+        assert!(check_pin_retries(3, 3, 0).is_err());
+    }
+}